@@ -1,41 +1,144 @@
+mod ao3;
 mod asstr;
 mod bdsmlibrary;
+mod chyoa;
+mod fanfiction;
+mod fictionmania;
 mod gaggedutopia;
+pub mod http;
+pub mod json;
+mod literotica;
+mod lushstories;
+mod mcstories;
+mod nifty;
+mod recipe;
+pub mod resolve;
+mod royalroad;
+mod script;
 mod sexstories;
+mod storiesonline;
 mod thefetlibrary;
+mod wattpad;
+mod wayback;
 
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use futures::{future::BoxFuture, ready, Stream, StreamExt};
+use tokio::runtime::Runtime;
+use url::Url;
 
 use crate::{
-    document::{Document, Meta},
+    document::{text::escape, Document, Meta, Metadata},
     Result,
 };
 
+use http::Http;
+
+pub use ao3::{Ao3Adapter, BuildAo3Adapter};
 pub use asstr::{AsstrAdapter, BuildAsstrAdapter};
 pub use bdsmlibrary::{BdsmLibraryAdapter, BuildBdsmLibraryAdapter};
+pub use chyoa::{BuildChyoaAdapter, ChyoaAdapter};
+pub use fanfiction::{BuildFanFictionAdapter, FanFictionAdapter};
+pub use fictionmania::{BuildFictionmaniaAdapter, FictionmaniaAdapter};
 pub use gaggedutopia::{BuildGaggedUtopiaAdapter, GaggedUtopiaAdapter};
+pub use literotica::{BuildLiteroticaAdapter, LiteroticaAdapter};
+pub use lushstories::{BuildLushStoriesAdapter, LushStoriesAdapter};
+pub use mcstories::{BuildMcStoriesAdapter, McStoriesAdapter};
+pub use nifty::{BuildNiftyAdapter, NiftyAdapter};
+pub use recipe::{
+    load_recipes, user_recipe_dir, BuildRecipeAdapter, DirectoryRecipe, DocumentRecipe, Extract,
+    Recipe, RecipeAdapter,
+};
+pub use royalroad::{BuildRoyalRoadAdapter, RoyalRoadAdapter};
+pub use script::{
+    load_scripts, user_script_dir, BuildScriptAdapter, Script, ScriptAdapter, ScriptLimits,
+};
 pub use sexstories::{BuildSexStoriesAdapter, SexStoriesAdapter};
+pub use storiesonline::{BuildStoriesOnlineAdapter, StoriesOnlineAdapter};
 pub use thefetlibrary::{BuildFetLibraryAdapter, FetLibraryAdapter};
+pub use wattpad::{BuildWattpadAdapter, WattpadAdapter};
+pub use wayback::{Wayback, WaybackAdapter};
 
 #[derive(Debug, Clone)]
 pub struct DocumentUrl {
-    meta: HashMap<Meta, String>,
+    /// Metadata of the directory this url was found in, shared between all its urls.
+    meta: Arc<Metadata>,
     url: String,
 }
 
-pub struct DirectoryUrls {
+impl DocumentUrl {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Merge metadata read from the document itself with that of its directory.
+    ///
+    /// Document metadata takes precedence; see `Metadata::merge`.
+    pub fn merge(&self, document: Metadata) -> Metadata {
+        let mut meta = Metadata::clone(&self.meta);
+        meta.merge(document);
+        meta
+    }
+}
+
+/// The urls of documents found in a directory, retrieved page by page as needed.
+pub struct DirectoryStream {
     urls: VecDeque<String>,
-    page: Option<Box<dyn Paging + 'static>>,
-    meta: HashMap<Meta, String>,
+    page: Option<Box<dyn AsyncPaging + 'static>>,
+    meta: Arc<Metadata>,
+    /// Metadata of individual urls, already merged with that of the directory.
+    documents: HashMap<String, Arc<Metadata>>,
+    pending: Option<PendingPage>,
 }
 
-impl std::fmt::Debug for DirectoryUrls {
+type PendingPage = BoxFuture<
+    'static,
+    (
+        Box<dyn AsyncPaging + 'static>,
+        Option<Result<VecDeque<String>>>,
+    ),
+>;
+
+impl DirectoryStream {
+    pub fn new(urls: impl IntoIterator<Item = String>, meta: Metadata) -> Self {
+        Self {
+            urls: urls.into_iter().collect(),
+            page: None,
+            meta: Arc::new(meta),
+            documents: HashMap::new(),
+            pending: None,
+        }
+    }
+
+    /// Add metadata which only applies to one of the urls, e.g. its position in a series.
+    pub fn with_document_meta(mut self, url: impl Into<String>, meta: Metadata) -> Self {
+        let mut merged = Metadata::clone(&self.meta);
+        merged.merge(meta);
+        self.documents.insert(url.into(), Arc::new(merged));
+        self
+    }
+
+    /// Retrieve further urls from `page` once those already found are exhausted.
+    pub fn with_paging(mut self, page: impl AsyncPaging + 'static) -> Self {
+        self.page = Some(Box::new(page));
+        self
+    }
+}
+
+impl std::fmt::Debug for DirectoryStream {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("DirectoryUrls")
+        f.debug_struct("DirectoryStream")
             .field("urls", &self.urls)
             .field(
                 "page",
-                &if self.page.is_some() {
+                &if self.page.is_some() || self.pending.is_some() {
                     "Some(Page)"
                 } else {
                     "None"
@@ -46,49 +149,347 @@ impl std::fmt::Debug for DirectoryUrls {
     }
 }
 
-pub trait Paging {
-    fn next_page(&mut self) -> Option<Result<VecDeque<String>>>;
+#[async_trait]
+pub trait AsyncPaging: Send {
+    async fn next_page(&mut self) -> Option<Result<VecDeque<String>>>;
+}
+
+/// One page of a listing: the urls found on it, and the page after it if there is one.
+pub(crate) struct Listing {
+    pub urls: VecDeque<String>,
+    pub next: Option<Url>,
+}
+
+/// Paging through a listing in which each page links to the next, reading each page with
+/// `parse`. Paging stops at the last page, or at a page we have been to before.
+pub(crate) struct LinkPaging<F> {
+    http: Http,
+    visited: HashSet<Url>,
+    next: Option<Url>,
+    parse: F,
+}
+
+impl<F> LinkPaging<F>
+where
+    F: Fn(&Url, &str) -> Result<Listing> + Send,
+{
+    /// Page on from `first`, which the caller has read already, to `next`.
+    pub fn new(http: Http, first: Url, next: Option<Url>, parse: F) -> Self {
+        Self {
+            http,
+            visited: Some(first).into_iter().collect(),
+            next,
+            parse,
+        }
+    }
+}
+
+#[async_trait]
+impl<F> AsyncPaging for LinkPaging<F>
+where
+    F: Fn(&Url, &str) -> Result<Listing> + Send,
+{
+    async fn next_page(&mut self) -> Option<Result<VecDeque<String>>> {
+        let page = self.next.take()?;
+        if !self.visited.insert(page.clone()) {
+            return None;
+        }
+
+        let listing = self
+            .http
+            .text(page.as_str())
+            .await
+            .and_then(|text| (self.parse)(&page, &text));
+        match listing {
+            Ok(listing) => {
+                self.next = listing.next;
+                Some(Ok(listing.urls))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// The start of a document's html: its title, and its author if we know who that is.
+pub(crate) fn document_header(meta: &Metadata) -> String {
+    let mut buf = String::new();
+    let title = escape(meta.text(&Meta::Title).unwrap_or_default());
+    writeln!(
+        buf,
+        "<title>{title}</title>\n<h1>{title}</h1>",
+        title = title
+    )
+    .unwrap();
+    if let Some(author) = meta.text(&Meta::Author) {
+        writeln!(buf, "<p>By <span id=author>{}</span></p>", escape(author)).unwrap();
+    }
+    buf
+}
+
+impl Stream for DirectoryStream {
+    type Item = Result<DocumentUrl>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(url) = self.urls.pop_front() {
+                let meta = match self.documents.get(&url) {
+                    Some(meta) => meta.clone(),
+                    None => self.meta.clone(),
+                };
+                return Poll::Ready(Some(Ok(DocumentUrl { meta, url })));
+            }
+
+            // The paging future needs to own the pager while it runs, so we hand the pager
+            // over and take it back once the page has been retrieved.
+            if self.pending.is_none() {
+                let mut page = match self.page.take() {
+                    Some(page) => page,
+                    None => return Poll::Ready(None),
+                };
+                self.pending = Some(Box::pin(async move {
+                    let urls = page.next_page().await;
+                    (page, urls)
+                }));
+            }
+
+            let (page, urls) = ready!(self.pending.as_mut().unwrap().as_mut().poll(cx));
+            self.pending = None;
+            match urls {
+                Some(Ok(urls)) => {
+                    self.page = Some(page);
+                    self.urls = urls;
+                }
+                Some(Err(e)) => {
+                    self.page = Some(page);
+                    return Poll::Ready(Some(Err(e)));
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Blocking access to the urls of a `DirectoryStream`.
+pub struct DirectoryUrls {
+    stream: DirectoryStream,
+    runtime: Arc<Runtime>,
+}
+
+impl std::fmt::Debug for DirectoryUrls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.stream.fmt(f)
+    }
 }
 
 impl Iterator for DirectoryUrls {
     type Item = Result<DocumentUrl>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.urls.is_empty() {
-            match self.page.as_mut()?.next_page()? {
-                Ok(urls) => self.urls = urls,
-                Err(e) => return Some(Err(e)),
-            }
-            return self.next();
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+/// Builders for every built-in adapter, keyed by the host they serve.
+pub fn builtin() -> Vec<(&'static str, Box<dyn BuildAdapter + 'static>)> {
+    vec![
+        ("archiveofourown.org", Box::new(BuildAo3Adapter)),
+        ("chyoa.com", Box::new(BuildChyoaAdapter)),
+        ("fictionmania.tv", Box::new(BuildFictionmaniaAdapter)),
+        ("mcstories.com", Box::new(BuildMcStoriesAdapter)),
+        ("storiesonline.net", Box::new(BuildStoriesOnlineAdapter)),
+        ("www.asstr.org", Box::new(BuildAsstrAdapter)),
+        ("www.bdsmlibrary.com", Box::new(BuildBdsmLibraryAdapter)),
+        ("www.fanfiction.net", Box::new(BuildFanFictionAdapter)),
+        ("www.fictionpress.com", Box::new(BuildFanFictionAdapter)),
+        ("www.literotica.com", Box::new(BuildLiteroticaAdapter)),
+        ("www.lushstories.com", Box::new(BuildLushStoriesAdapter)),
+        ("www.nifty.org", Box::new(BuildNiftyAdapter)),
+        ("www.royalroad.com", Box::new(BuildRoyalRoadAdapter)),
+        ("www.sexstories.com", Box::new(BuildSexStoriesAdapter)),
+        ("www.thefetlibrary.com", Box::new(BuildFetLibraryAdapter)),
+        ("www.utopiastories.com", Box::new(BuildGaggedUtopiaAdapter)),
+        ("www.wattpad.com", Box::new(BuildWattpadAdapter)),
+    ]
+}
+
+/// Everything an adapter is built with.
+#[derive(Clone, Default)]
+pub struct AdapterOptions {
+    /// Where the adapter makes its requests.
+    pub http: Http,
+    /// The site's base url, in place of the one the adapter was written for, e.g. a mirror.
+    ///
+    /// This is used for urls an adapter builds itself; links found on a page are always
+    /// resolved against that page.
+    pub base: Option<Url>,
+    /// Settings given by the user, e.g. `literotica.series=merge`; each adapter documents those
+    /// it reads, named after itself.
+    pub settings: Arc<HashMap<String, String>>,
+}
+
+impl AdapterOptions {
+    /// The configured base url, or `default` if none was given.
+    ///
+    /// Adapters join paths onto it, so a base given without its trailing slash, e.g.
+    /// `https://mirror.example.com/archive`, is taken as the directory it names.
+    pub fn base_or(&self, default: &str) -> Url {
+        match &self.base {
+            Some(base) => resolve::directory(base),
+            None => Url::parse(default).unwrap(),
         }
+    }
+
+    pub fn setting(&self, key: &str) -> Option<&str> {
+        self.settings.get(key).map(String::as_str)
+    }
+}
+
+pub trait BuildAdapter: Send + Sync {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static>;
 
-        self.urls
-            .pop_front()
-            .map(|url| DocumentUrl {
-                meta: self.meta.clone(),
-                url,
-            })
-            .map(Ok)
+    /// Build a blocking adapter, which runs the async adapter on its own runtime.
+    ///
+    /// Blocking adapters must not be used from within an async context.
+    fn build(&self, options: AdapterOptions) -> Box<dyn Adapter + 'static> {
+        Box::new(Blocking::new(self.build_async(options)))
     }
 }
 
-pub trait BuildAdapter {
-    fn build(&self) -> Box<dyn Adapter + 'static>;
+#[async_trait]
+pub trait AsyncAdapter: Send + Sync {
+    /// A short, stable name identifying the adapter.
+    fn name(&self) -> &str;
+    async fn directory(&self, url: &str) -> Result<DirectoryStream>;
+    async fn download(&self, context: DocumentUrl) -> Result<Document>;
 }
 
 pub trait Adapter {
+    /// A short, stable name identifying the adapter.
+    fn name(&self) -> &str;
     fn directory(&self, url: &str) -> Result<DirectoryUrls>;
     fn download(&self, context: DocumentUrl) -> Result<Document>;
 }
 
+/// A blocking adapter wrapping an async one.
+pub struct Blocking {
+    adapter: Box<dyn AsyncAdapter + 'static>,
+    runtime: Arc<Runtime>,
+}
+
+impl Blocking {
+    pub fn new(adapter: Box<dyn AsyncAdapter + 'static>) -> Self {
+        Self {
+            adapter,
+            runtime: Arc::new(runtime()),
+        }
+    }
+}
+
+impl Adapter for Blocking {
+    fn name(&self) -> &str {
+        self.adapter.name()
+    }
+
+    fn directory(&self, url: &str) -> Result<DirectoryUrls> {
+        Ok(DirectoryUrls {
+            stream: self.runtime.block_on(self.adapter.directory(url))?,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    fn download(&self, context: DocumentUrl) -> Result<Document> {
+        self.runtime.block_on(self.adapter.download(context))
+    }
+}
+
+/// The runtime used to drive async adapters from blocking code.
+pub(crate) fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
 mod prelude {
-    pub static USER_AGENT: &str = "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:93.0) Gecko/20100101 Firefox/93.0";
-    pub use super::{Adapter, BuildAdapter, DirectoryUrls, DocumentUrl};
+    pub static USER_AGENT: &str =
+        "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:93.0) Gecko/20100101 Firefox/93.0";
+    pub(crate) use super::{document_header, LinkPaging, Listing};
+    pub use super::{
+        http::Http, json, resolve, AdapterOptions, AsyncAdapter, BuildAdapter, DirectoryStream,
+        DocumentUrl,
+    };
     pub use crate::{
-        document::{Document, Meta},
+        document::{Document, Meta, Metadata, Value},
         Result,
     };
-    pub use regex::{Regex, RegexBuilder};
-    pub use reqwest::blocking::Client;
-    pub use std::collections::HashMap;
+    pub use async_trait::async_trait;
+    pub use regex::Regex;
+    pub use serde::Deserialize;
+    pub use url::Url;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use async_trait::async_trait;
+    use futures::StreamExt;
+
+    use url::Url;
+
+    use super::{AdapterOptions, AsyncPaging, DirectoryStream};
+    use crate::{document::Metadata, error::Error, Result};
+
+    struct Pages(VecDeque<Result<VecDeque<String>>>);
+
+    #[async_trait]
+    impl AsyncPaging for Pages {
+        async fn next_page(&mut self) -> Option<Result<VecDeque<String>>> {
+            self.0.pop_front()
+        }
+    }
+
+    #[test]
+    fn directory_stream_pages() {
+        let pages = Pages(VecDeque::from(vec![
+            Ok(VecDeque::from(vec!["b".to_string(), "c".to_string()])),
+            Err(Error::MissingDomain("bad page".into())),
+            Ok(VecDeque::from(vec!["d".to_string()])),
+        ]));
+        let stream =
+            DirectoryStream::new(vec!["a".to_string()], Metadata::new()).with_paging(pages);
+
+        let urls: Vec<_> = super::runtime().block_on(stream.collect());
+        let urls: Vec<_> = urls
+            .into_iter()
+            .map(|url| url.map(|url| url.url().to_owned()).ok())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                Some("a".into()),
+                Some("b".into()),
+                Some("c".into()),
+                None,
+                Some("d".into())
+            ]
+        );
+    }
+    #[test]
+    fn base_is_a_directory() {
+        let options = AdapterOptions {
+            base: Some(Url::parse("https://mirror.example.com/archive").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.base_or("https://www.example.com/").as_str(),
+            "https://mirror.example.com/archive/"
+        );
+        assert_eq!(
+            AdapterOptions::default()
+                .base_or("https://www.example.com/")
+                .as_str(),
+            "https://www.example.com/"
+        );
+    }
 }
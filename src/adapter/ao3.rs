@@ -0,0 +1,242 @@
+//! Archive of Our Own: works (`/works/<id>`, or any chapter of one), series (`/series/<id>`),
+//! and a user's works and bookmarks (`/users/<name>/works`, `/users/<name>/bookmarks`).
+//!
+//! Works are always retrieved whole (`view_full_work=true`) and past the adult content notice
+//! (`view_adult=true`).
+
+use std::{collections::HashSet, fmt::Write};
+
+use super::prelude::*;
+use crate::document::text::escape;
+
+pub struct BuildAo3Adapter;
+
+impl BuildAdapter for BuildAo3Adapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(Ao3Adapter::new(options))
+    }
+}
+
+pub struct Ao3Adapter {
+    http: Http,
+    base: Url,
+    position: Regex,
+}
+
+impl Ao3Adapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self {
+            base: options.base_or("https://archiveofourown.org/"),
+            http: options.http,
+            position: Regex::new(r"Part (\d+) of").unwrap(),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for Ao3Adapter {
+    fn name(&self) -> &'static str {
+        "ao3"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let page = Url::parse(url)?;
+        if let Some(work) = work_url(&self.base, &page) {
+            return Ok(DirectoryStream::new(Some(work.into()), Metadata::new()));
+        }
+
+        let text = self.http.text(url).await?;
+        let first = listing(&self.base, &page, &text);
+        let mut meta = Metadata::new();
+        let mut directory = if page.path().starts_with("/series/") {
+            let document = nipper::Document::from(&text);
+            let series = document.select("h2.heading").first().text();
+            if !series.trim().is_empty() {
+                meta.insert(Meta::Series, series.trim());
+            }
+
+            // Works are listed in series order. Later pages go without an index, since we
+            // cannot tell how many works came before them.
+            let mut directory = DirectoryStream::new(first.urls.clone(), meta);
+            for (idx, url) in first.urls.into_iter().enumerate() {
+                let mut meta = Metadata::new();
+                meta.insert(Meta::SeriesIndex, Value::Integer(idx as i64 + 1));
+                directory = directory.with_document_meta(url, meta);
+            }
+            directory
+        } else {
+            DirectoryStream::new(first.urls, meta)
+        };
+
+        if first.next.is_some() {
+            let base = self.base.clone();
+            directory = directory.with_paging(LinkPaging::new(
+                self.http.clone(),
+                page,
+                first.next,
+                move |page, text| Ok(listing(&base, page, text)),
+            ));
+        }
+        Ok(directory)
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let mut url = Url::parse(context.url())?;
+        url.query_pairs_mut()
+            .append_pair("view_adult", "true")
+            .append_pair("view_full_work", "true");
+        let text = self.http.text(url.as_str()).await?;
+
+        let document = nipper::Document::from(&text);
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+        if let Some(title) = text_of(&document, "h2.title.heading") {
+            meta.insert(Meta::Title, title);
+        }
+        let authors = tags(&document, "h3.byline.heading a[rel='author']");
+        if !authors.is_empty() {
+            meta.insert(Meta::Author, authors.join(", "));
+        }
+        if let Some(summary) = text_of(&document, "div.preface div.summary blockquote.userstuff") {
+            meta.insert(Meta::Summary, summary);
+        }
+
+        for (selector, key) in [
+            ("dd.rating.tags a.tag", Meta::Rating),
+            ("dd.language", Meta::Language),
+            ("dd.published", Meta::PublicationDate),
+            ("dd.status", Meta::LastUpdated),
+            ("dd.words", Meta::WordCount),
+        ] {
+            if let Some(text) = text_of(&document, selector) {
+                meta.insert(key.clone(), key.parse(&text));
+            }
+        }
+        for (selector, key) in [
+            ("dd.warning.tags a.tag", Meta::Other("warnings".into())),
+            ("dd.category.tags a.tag", Meta::Categories),
+            ("dd.fandom.tags a.tag", Meta::Other("fandoms".into())),
+            (
+                "dd.relationship.tags a.tag",
+                Meta::Other("relationships".into()),
+            ),
+            ("dd.character.tags a.tag", Meta::Other("characters".into())),
+            ("dd.freeform.tags a.tag", Meta::Tags),
+        ] {
+            let tags = tags(&document, selector);
+            if !tags.is_empty() {
+                meta.insert(key, Value::list(tags));
+            }
+        }
+
+        // "2/2" is complete, while "2/5" and "2/?" are still being written.
+        if let Some((written, planned)) = text_of(&document, "dd.chapters")
+            .as_deref()
+            .and_then(|chapters| chapters.split_once('/'))
+        {
+            let status = if written.trim() == planned.trim() {
+                "complete"
+            } else {
+                "ongoing"
+            };
+            meta.insert(Meta::Status, status);
+        }
+
+        // A work can be part of several series; prefer the one it was found through.
+        let wanted = context.meta.text(&Meta::Series);
+        let series = document
+            .select("dd.series span.position")
+            .iter()
+            .filter_map(|position| {
+                let name = position.select("a").text().trim().to_owned();
+                let index = self
+                    .position
+                    .captures(&position.text())?
+                    .get(1)?
+                    .as_str()
+                    .to_owned();
+                Some((name, index))
+            })
+            .find(|(name, _)| wanted.map(|wanted| wanted == name).unwrap_or(true));
+        if let Some((name, index)) = series {
+            meta.insert(Meta::Series, name);
+            meta.insert(Meta::SeriesIndex, Value::integer(&index));
+        }
+
+        // Chapter text starts with a heading for screen readers, which we leave out.
+        document.select("div.userstuff h3.landmark").remove();
+        let mut content = String::new();
+        let chapters = document.select("div#chapters > div.chapter");
+        if chapters.length() == 0 {
+            writeln!(
+                content,
+                "{}",
+                document
+                    .select("div#chapters > div.userstuff")
+                    .html()
+                    .trim()
+            )
+            .unwrap();
+        }
+        for chapter in chapters.iter() {
+            writeln!(
+                content,
+                "<h2>{}</h2>\n{}",
+                escape(chapter.select("h3.title").text().trim()),
+                chapter.select("div.userstuff").html().trim()
+            )
+            .unwrap();
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        buf.push_str(&content);
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+/// One page of a listing of works. Bookmark listings may also hold series and external works,
+/// which we skip.
+fn listing(base: &Url, page: &Url, text: &str) -> Listing {
+    let document = nipper::Document::from(text);
+    let mut seen = HashSet::new();
+    let urls = document
+        .select("li.blurb h4.heading a")
+        .iter()
+        .filter_map(|link| resolve::link(page, &link.attr("href")?))
+        .filter_map(|url| work_url(base, &url))
+        .filter(|url| seen.insert(url.clone()))
+        .map(String::from)
+        .collect();
+    let next = document
+        .select("ol.pagination li.next a[rel='next']")
+        .attr("href")
+        .and_then(|href| resolve::link(page, &href));
+    Listing { urls, next }
+}
+
+/// The canonical url of the work a url points into, e.g. one of its chapters, if it does.
+fn work_url(base: &Url, url: &Url) -> Option<Url> {
+    let segments: Vec<_> = url.path_segments()?.filter(|x| !x.is_empty()).collect();
+    match segments[..] {
+        ["works", id] | ["works", id, "chapters", _] if id.chars().all(|u| u.is_ascii_digit()) => {
+            base.join(&format!("works/{}", id)).ok()
+        }
+        _ => None,
+    }
+}
+
+fn text_of(document: &nipper::Document, selector: &str) -> Option<String> {
+    let text = document.select(selector).first().text();
+    Some(text.trim().to_owned()).filter(|text| !text.is_empty())
+}
+
+fn tags(document: &nipper::Document, selector: &str) -> Vec<String> {
+    document
+        .select(selector)
+        .iter()
+        .map(|tag| tag.text().trim().to_owned())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
@@ -1,29 +1,38 @@
+use std::borrow::Cow;
+
+use percent_encoding::percent_decode_str;
+
 use super::prelude::*;
+use crate::document::text;
 
 pub struct BuildAsstrAdapter;
 
 impl BuildAdapter for BuildAsstrAdapter {
-    fn build(&self) -> Box<dyn Adapter + 'static> {
-        Box::new(AsstrAdapter::new())
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(AsstrAdapter::new(options))
     }
 }
 
 pub struct AsstrAdapter {
-    client: Client,
+    http: Http,
 }
 
 impl AsstrAdapter {
-    fn new() -> Self {
-        Self {
-            client: Client::builder().user_agent(USER_AGENT).build().unwrap(),
-        }
+    fn new(options: AdapterOptions) -> Self {
+        Self { http: options.http }
     }
 }
 
-impl Adapter for AsstrAdapter {
-    fn directory(&self, url: &str) -> Result<DirectoryUrls> {
-        let text = self.client.get(url).send()?.text()?;
+#[async_trait]
+impl AsyncAdapter for AsstrAdapter {
+    fn name(&self) -> &'static str {
+        "asstr"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let text = self.http.text(url).await?;
         let document = nipper::Document::from(&text);
+        let page = resolve::directory(&Url::parse(url)?);
 
         // The first item is "../" and actually just goes up one directory. I could just
         // call .skip, but what if I run into a listing where that's not present?!
@@ -31,30 +40,51 @@ impl Adapter for AsstrAdapter {
             .select("td.link > a")
             .iter()
             .filter_map(|item| item.attr("href"))
-            .filter(|link| "../" != link.as_ref());
-
-        Ok(DirectoryUrls {
-            urls: items
-                .map(|route| url.to_string() + route.as_ref())
-                .collect(),
-            page: None,
-            meta: HashMap::new(),
-        })
+            .filter(|link| "../" != link.as_ref())
+            .filter_map(|href| resolve::link(&page, &href));
+
+        Ok(DirectoryStream::new(
+            items.map(String::from),
+            Metadata::new(),
+        ))
     }
 
-    fn download(&self, context: DocumentUrl) -> Result<Document> {
-        let text = self.client.get(&context.url).send()?.text()?;
-        let mut meta = context.meta;
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let response = self.http.get(&context.url).await?;
+        let is_html = response.is_html();
+        let text = response.body;
+
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(&context.url));
         let name = name_from_url(&context.url);
-        meta.insert(Meta::Title, name.into());
-        Ok(Document { meta, text })
+
+        // Most of what's on asstr is plain text, which we wrap up as html so that it reads
+        // the same as everything else.
+        if is_html {
+            meta.insert(Meta::Title, &*name);
+            Ok(Document {
+                meta: context.merge(meta),
+                text,
+            })
+        } else {
+            let name = name.strip_suffix(".txt").unwrap_or(&name);
+            meta.insert(Meta::Title, name);
+
+            // Parts of a multi-part story tend to share a header title, so we keep the file
+            // name as our title and take everything else from the header.
+            let mut header = text::header_meta(&text);
+            header.remove(&Meta::Title);
+            header.merge(meta);
+            Ok(Document::from_text(context.merge(header), &text))
+        }
     }
 }
 
-fn name_from_url(url: &str) -> &str {
+/// The file name at the end of a url, decoded since listing links come to us percent-encoded.
+fn name_from_url(url: &str) -> Cow<'_, str> {
     let left = url.rfind('/').map(|idx| idx + 1).unwrap_or_default();
     let right = url.rfind('?').unwrap_or(url.len());
-    &url[left..right]
+    percent_decode_str(&url[left..right]).decode_utf8_lossy()
 }
 
 #[cfg(test)]
@@ -66,5 +96,9 @@ mod tests {
 
         let actual = super::name_from_url("hello.txt");
         assert_eq!("hello.txt", actual);
+
+        let actual =
+            super::name_from_url("https://www.asstr.org/files/Old%20Joe's%20Story%C3%A9.txt");
+        assert_eq!("Old Joe's Storyé.txt", actual);
     }
 }
@@ -3,37 +3,48 @@ use super::prelude::*;
 pub struct BuildBdsmLibraryAdapter;
 
 impl BuildAdapter for BuildBdsmLibraryAdapter {
-    fn build(&self) -> Box<dyn Adapter + 'static> {
-        Box::new(BdsmLibraryAdapter::new())
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(BdsmLibraryAdapter::new(options))
     }
 }
 
 pub struct BdsmLibraryAdapter {
-    client: Client,
+    http: Http,
+    base: Url,
     author_pattern: Regex,
     story_id_pattern: Regex,
     title_pattern: Regex,
+    synopsis_pattern: Regex,
+    codes_pattern: Regex,
 }
 
 impl BdsmLibraryAdapter {
-    fn new() -> Self {
+    fn new(options: AdapterOptions) -> Self {
         Self {
-            client: Client::builder().user_agent(USER_AGENT).build().unwrap(),
+            base: options.base_or("https://www.bdsmlibrary.com/"),
+            http: options.http,
             author_pattern: Regex::new(r"<title>BDSM Library - Stories by ([^<]+)</title>")
                 .unwrap(),
             story_id_pattern: Regex::new(r"story\.php\?storyid=(\d+)").unwrap(),
             title_pattern: Regex::new(r"<title>([^<]+)</title>").unwrap(),
+            synopsis_pattern: Regex::new(r"Synopsis:\s*(?:<[^>]+>\s*)*([^<]+)").unwrap(),
+            codes_pattern: Regex::new(r"Codes:\s*(?:<[^>]+>\s*)*([^<]+)").unwrap(),
         }
     }
 }
 
-impl Adapter for BdsmLibraryAdapter {
-    fn directory(&self, url: &str) -> Result<DirectoryUrls> {
-        let content = self.client.get(url).send()?.text()?;
+#[async_trait]
+impl AsyncAdapter for BdsmLibraryAdapter {
+    fn name(&self) -> &'static str {
+        "bdsmlibrary"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let content = self.http.text(url).await?;
         let author = self
             .author_pattern
             .captures(&content)
-            .and_then(|captures| captures.get(0))
+            .and_then(|captures| captures.get(1))
             .map(|x| x.as_str().to_owned());
         let story_ids = self
             .story_id_pattern
@@ -44,40 +55,59 @@ impl Adapter for BdsmLibraryAdapter {
                     .map(|capture| StoryId(capture.as_str().into()))
             });
 
-        let mut meta = HashMap::new();
+        let mut meta = Metadata::new();
         if let Some(author) = author {
             meta.insert(Meta::Author, author);
         }
 
-        Ok(DirectoryUrls {
-            urls: story_ids.map(|id| id.url()).collect(),
-            page: None,
-            meta,
-        })
+        let urls = story_ids
+            .map(|id| id.url(&self.base))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DirectoryStream::new(urls, meta))
     }
 
-    fn download(&self, context: DocumentUrl) -> Result<Document> {
-        let text = self.client.get(&context.url).send()?.text()?;
-        let mut meta = context.meta;
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let text = self.http.text(&context.url).await?;
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(&context.url));
         if let Some(title) = self
             .title_pattern
             .captures(&text)
             .and_then(|x| x.get(1).map(|x| x.as_str()))
         {
-            meta.insert(Meta::Title, title.into());
+            meta.insert(Meta::Title, title);
+        }
+
+        if let Some(synopsis) = self
+            .synopsis_pattern
+            .captures(&text)
+            .and_then(|x| x.get(1).map(|x| x.as_str().trim()))
+        {
+            meta.insert(Meta::Summary, synopsis);
         }
 
-        Ok(Document { meta, text })
+        // Story codes are space-separated, e.g. "MF Fd ds".
+        if let Some(codes) = self
+            .codes_pattern
+            .captures(&text)
+            .and_then(|x| x.get(1).map(|x| x.as_str()))
+        {
+            meta.insert(Meta::Tags, Value::list(codes.split_whitespace()));
+        }
+
+        Ok(Document {
+            meta: context.merge(meta),
+            text,
+        })
     }
 }
 
 struct StoryId(String);
 
 impl StoryId {
-    fn url(&self) -> String {
-        format!(
-            "https://www.bdsmlibrary.com/stories/wholestory.php?storyid={}",
-            self.0
-        )
+    fn url(&self, base: &Url) -> Result<String> {
+        let mut url = base.join("stories/wholestory.php")?;
+        url.query_pairs_mut().append_pair("storyid", &self.0);
+        Ok(url.into())
     }
 }
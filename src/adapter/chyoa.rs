@@ -0,0 +1,239 @@
+//! CHYOA: interactive stories, where every chapter ends in a choice between further chapters.
+//!
+//! A story (`/story/<slug>.<id>`) or any chapter of it (`/chapter/<slug>.<id>`) is retrieved as
+//! a single document holding every chapter reachable from it, each once even where several
+//! choices lead to it. Choices link to the chapters they lead to within the document.
+//!
+//! Settings:
+//!
+//! - `chyoa.path`: follow a single path instead, given as the number of the choice to take in
+//!   each chapter, counting from 1, e.g. `1,3,2`.
+//! - `chyoa.max-chapters`: stop after this many chapters (default 500). A story cut short
+//!   says so in its `truncated` metadata.
+//!
+//! A setting that doesn't parse fails the download with `Error::Setting`.
+
+use std::{collections::HashMap, fmt::Write};
+
+use super::prelude::*;
+use crate::{document::text::escape, error::Error};
+
+const MAX_CHAPTERS: usize = 500;
+
+pub struct BuildChyoaAdapter;
+
+impl BuildAdapter for BuildChyoaAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(ChyoaAdapter::new(options))
+    }
+}
+
+pub struct ChyoaAdapter {
+    http: Http,
+    path: Option<String>,
+    max_chapters: Option<String>,
+}
+
+impl ChyoaAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self {
+            path: options.setting("chyoa.path").map(ToOwned::to_owned),
+            max_chapters: options.setting("chyoa.max-chapters").map(ToOwned::to_owned),
+            http: options.http,
+        }
+    }
+
+    /// The choice to take in each chapter, if following a single path. Settings are only
+    /// checked here, so that a bad one fails the download rather than changing what it gets.
+    fn path(&self) -> Result<Option<Vec<usize>>> {
+        self.path
+            .as_deref()
+            .map(|path| {
+                path.split(',')
+                    .map(|choice| choice.trim().parse().ok().filter(|choice| *choice > 0))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| {
+                        Error::Setting(
+                            "chyoa.path".into(),
+                            format!("not a list of choices: {}", path),
+                        )
+                    })
+            })
+            .transpose()
+    }
+
+    fn max_chapters(&self) -> Result<usize> {
+        match &self.max_chapters {
+            Some(max) => max.trim().parse().map_err(|_| {
+                Error::Setting(
+                    "chyoa.max-chapters".into(),
+                    format!("not a number: {}", max),
+                )
+            }),
+            None => Ok(MAX_CHAPTERS),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for ChyoaAdapter {
+    fn name(&self) -> &'static str {
+        "chyoa"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        Ok(DirectoryStream::new(Some(url.to_owned()), Metadata::new()))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let path = self.path()?;
+        let max_chapters = self.max_chapters()?;
+        let start = Url::parse(context.url())?;
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+
+        // Chapters are visited breadth first, so that they appear in the document roughly in
+        // the order a reader would reach them. `order` holds every chapter found so far, of
+        // which the first `chapters.len()` have been retrieved.
+        let mut order = vec![start.clone()];
+        let mut found: HashMap<Url, usize> = Some((start, 0)).into_iter().collect();
+        let mut chapters = Vec::new();
+        while chapters.len() < order.len().min(max_chapters) {
+            let page = order[chapters.len()].clone();
+            let text = self.http.text(page.as_str()).await?;
+            let chapter = {
+                let document = nipper::Document::from(&text);
+                if chapters.is_empty() {
+                    meta.merge(story_meta(&document));
+                }
+                Chapter::parse(&document, &page)
+            };
+
+            let next: Vec<&Url> = match &path {
+                Some(path) => path
+                    .get(chapters.len())
+                    .and_then(|choice| chapter.choices.get(choice - 1))
+                    .map(|(_, url)| url)
+                    .into_iter()
+                    .collect(),
+                None => chapter.choices.iter().map(|(_, url)| url).collect(),
+            };
+            for url in next {
+                if !found.contains_key(url) {
+                    found.insert(url.clone(), order.len());
+                    order.push(url.clone());
+                }
+            }
+            chapters.push(chapter);
+        }
+        if chapters.len() < order.len() {
+            meta.insert(
+                Meta::Other("truncated".into()),
+                format!(
+                    "stopped after {} chapters; set chyoa.max-chapters to retrieve more",
+                    chapters.len()
+                ),
+            );
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+
+        for (idx, chapter) in chapters.iter().enumerate() {
+            writeln!(
+                buf,
+                "<section id=chapter-{}>\n<h2>{}</h2>\n{}",
+                idx + 1,
+                escape(&chapter.title),
+                chapter.content
+            )
+            .unwrap();
+            if !chapter.choices.is_empty() {
+                writeln!(
+                    buf,
+                    "<p class=question>{}</p>\n<ul>",
+                    escape(&chapter.question)
+                )
+                .unwrap();
+                for (text, url) in &chapter.choices {
+                    // Choices leading outside what we retrieved keep pointing at the site.
+                    let href = match found.get(url).filter(|idx| **idx < chapters.len()) {
+                        Some(idx) => format!("#chapter-{}", idx + 1),
+                        None => url.to_string(),
+                    };
+                    writeln!(
+                        buf,
+                        "<li><a href=\"{}\">{}</a></li>",
+                        escape(&href),
+                        escape(text)
+                    )
+                    .unwrap();
+                }
+                writeln!(buf, "</ul>").unwrap();
+            }
+            writeln!(buf, "</section>").unwrap();
+        }
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+/// One node of a story.
+struct Chapter {
+    title: String,
+    content: String,
+    question: String,
+    /// The text of each choice and the chapter it leads to.
+    choices: Vec<(String, Url)>,
+}
+
+impl Chapter {
+    fn parse(document: &nipper::Document, page: &Url) -> Self {
+        let choices = document
+            .select("div.question-content a")
+            .iter()
+            .filter_map(|link| {
+                let url = resolve::link(page, &link.attr("href")?)?;
+                url.path()
+                    .starts_with("/chapter/")
+                    .then(|| (link.text().trim().to_owned(), url))
+            })
+            .collect();
+
+        Self {
+            title: document.select("h1").first().text().trim().to_owned(),
+            content: document
+                .select("div.chapter-content")
+                .html()
+                .trim()
+                .to_owned(),
+            question: document
+                .select("header.question-header")
+                .text()
+                .trim()
+                .to_owned(),
+            choices,
+        }
+    }
+}
+
+fn story_meta(document: &nipper::Document) -> Metadata {
+    let mut meta = Metadata::new();
+    let title = document.select("h1").first().text();
+    if !title.trim().is_empty() {
+        meta.insert(Meta::Title, title.trim());
+    }
+    let author = document.select("p.meta a[href*='/user/']").first().text();
+    if !author.trim().is_empty() {
+        meta.insert(Meta::Author, author.trim());
+    }
+    let tags: Vec<_> = document
+        .select("ul.tags a")
+        .iter()
+        .map(|tag| tag.text().to_string())
+        .collect();
+    if !tags.is_empty() {
+        meta.insert(Meta::Tags, Value::list(tags));
+    }
+    meta
+}
@@ -0,0 +1,281 @@
+//! FanFiction.net and FictionPress, which share a site: stories (`/s/<id>/<chapter>/<slug>`),
+//! authors (`/u/<id>/<name>`) and listings such as a fandom's stories (`/book/<fandom>/`).
+//!
+//! A story's chapters are found through its chapter select box and joined into one document.
+//! Both sites sit behind Cloudflare, whose challenge pages are reported as
+//! `Error::Challenge` by the http layer rather than read as story text.
+
+use std::{collections::HashSet, fmt::Write};
+
+use chrono::DateTime;
+
+use super::prelude::*;
+use crate::document::text::escape;
+
+const GENRES: &[&str] = &[
+    "Adventure",
+    "Angst",
+    "Crime",
+    "Drama",
+    "Family",
+    "Fantasy",
+    "Friendship",
+    "General",
+    "Horror",
+    "Humor",
+    "Hurt/Comfort",
+    "Mystery",
+    "Parody",
+    "Poetry",
+    "Romance",
+    "Sci-Fi",
+    "Spiritual",
+    "Supernatural",
+    "Suspense",
+    "Tragedy",
+    "Western",
+];
+
+pub struct BuildFanFictionAdapter;
+
+impl BuildAdapter for BuildFanFictionAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(FanFictionAdapter::new(options))
+    }
+}
+
+pub struct FanFictionAdapter {
+    http: Http,
+}
+
+impl FanFictionAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self { http: options.http }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for FanFictionAdapter {
+    fn name(&self) -> &'static str {
+        "fanfiction"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let page = Url::parse(url)?;
+        if let Some(story) = story_url(&page, 1) {
+            return Ok(DirectoryStream::new(Some(story.into()), Metadata::new()));
+        }
+
+        let text = self.http.text(url).await?;
+        let first = listing(&page, &text);
+        let mut directory = DirectoryStream::new(first.urls, Metadata::new());
+        if first.next.is_some() {
+            directory = directory.with_paging(LinkPaging::new(
+                self.http.clone(),
+                page,
+                first.next,
+                |page, text| Ok(listing(page, text)),
+            ));
+        }
+        Ok(directory)
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let page = Url::parse(context.url())?;
+        let text = self.http.text(page.as_str()).await?;
+
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+        let (first, chapters) = {
+            let document = nipper::Document::from(&text);
+            meta.merge(story_meta(&document));
+
+            // Each option is numbered, e.g. "2. The Second Chapter".
+            let chapters: Vec<(usize, String)> = document
+                .select("select#chap_select")
+                .first()
+                .select("option")
+                .iter()
+                .filter_map(|option| {
+                    let number: usize = option.attr("value")?.parse().ok()?;
+                    let title = option.text();
+                    let title = match title.split_once(". ") {
+                        Some((prefix, title)) if prefix.trim() == number.to_string() => title,
+                        _ => &title,
+                    };
+                    Some((number, title.trim().to_owned()))
+                })
+                .collect();
+            (chapter_text(&document), chapters)
+        };
+
+        let mut content = String::new();
+        if chapters.len() <= 1 {
+            writeln!(content, "{}", first).unwrap();
+        }
+        for (number, title) in chapters.iter().filter(|_| chapters.len() > 1) {
+            let text = match story_url(&page, *number) {
+                Some(url) if *number != 1 => {
+                    let text = self.http.text(url.as_str()).await?;
+                    chapter_text(&nipper::Document::from(&text))
+                }
+                _ => first.clone(),
+            };
+            writeln!(content, "<h2>{}</h2>\n{}", escape(title), text).unwrap();
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        buf.push_str(&content);
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+fn chapter_text(document: &nipper::Document) -> String {
+    document.select("div#storytext").html().trim().to_owned()
+}
+
+fn story_meta(document: &nipper::Document) -> Metadata {
+    let mut meta = Metadata::new();
+    let profile = document.select("div#profile_top");
+    for (selector, key) in [
+        ("b.xcontrast_txt", Meta::Title),
+        ("a.xcontrast_txt[href^='/u/']", Meta::Author),
+        ("div.xcontrast_txt", Meta::Summary),
+    ] {
+        let text = profile.select(selector).first().text();
+        if !text.trim().is_empty() {
+            meta.insert(key, text.trim());
+        }
+    }
+
+    // The breadcrumb leads from the section of the site to the fandom, e.g. "Books > Dune";
+    // crossovers name both fandoms in one link.
+    let crumbs: Vec<_> = document
+        .select("div#pre_story_links a")
+        .iter()
+        .map(|link| link.text().trim().to_owned())
+        .collect();
+    if let Some((fandom, sections)) = crumbs.split_last() {
+        let fandoms = fandom
+            .strip_suffix(" Crossover")
+            .map(|fandoms| fandoms.split(" + ").collect())
+            .unwrap_or_else(|| vec![fandom.as_str()]);
+        meta.insert(Meta::Other("fandoms".into()), Value::list(fandoms));
+        if !sections.is_empty() {
+            meta.insert(Meta::Categories, Value::list(sections));
+        }
+    }
+
+    // A line of details, e.g. "Rated: Fiction T - English - Adventure/Humor - Chapters: 3 -
+    // Words: 12,345 - ... - Status: Complete - id: 123", where genres, characters and the
+    // status are only there if they apply.
+    let details = profile.select("span.xgray").first();
+    let text = details.text();
+    let fields: Vec<&str> = text.split(" - ").map(str::trim).collect();
+    for (idx, field) in fields.iter().enumerate() {
+        match field.split_once(':') {
+            Some(("Rated", rating)) => {
+                let rating = rating.trim();
+                let rating = rating.strip_prefix("Fiction").unwrap_or(rating);
+                meta.insert(Meta::Rating, rating.trim());
+            }
+            Some(("Words", words)) => {
+                meta.insert(Meta::WordCount, Value::integer(words));
+            }
+            Some(_) => {}
+            None if idx == 1 => {
+                meta.insert(Meta::Language, *field);
+            }
+            None => match genres(field).filter(|_| idx == 2) {
+                Some(genres) => {
+                    meta.insert(Meta::Other("genres".into()), Value::list(genres));
+                }
+                None => {
+                    meta.insert(Meta::Other("characters".into()), *field);
+                }
+            },
+        }
+    }
+
+    // Only finished stories have a status.
+    let complete = fields.contains(&"Status: Complete");
+    meta.insert(Meta::Status, if complete { "complete" } else { "ongoing" });
+
+    // Dates are given relative to now, e.g. "2h ago", but carry a timestamp. Stories that
+    // were updated have two, the update first.
+    let dates: Vec<_> = details
+        .select("span[data-xutime]")
+        .iter()
+        .filter_map(|date| {
+            let seconds = date.attr("data-xutime")?.parse().ok()?;
+            Some(DateTime::from_timestamp(seconds, 0)?.date_naive())
+        })
+        .collect();
+    match dates[..] {
+        [updated, published] => {
+            meta.insert(Meta::LastUpdated, Value::Date(updated));
+            meta.insert(Meta::PublicationDate, Value::Date(published));
+        }
+        [published] => {
+            meta.insert(Meta::PublicationDate, Value::Date(published));
+        }
+        _ => {}
+    }
+    meta
+}
+
+/// The genres in a field of a story's details, e.g. "Hurt/Comfort/Romance", if that is what
+/// it holds. Genres are separated by slashes, which one of them also contains.
+fn genres(field: &str) -> Option<Vec<&'static str>> {
+    let mut genres = Vec::new();
+    let mut rest = field;
+    while !rest.is_empty() {
+        let genre = GENRES.iter().find(|genre| rest.starts_with(*genre))?;
+        rest = rest[genre.len()..].trim_start_matches('/');
+        genres.push(*genre);
+    }
+    Some(genres)
+}
+
+/// One page of a listing of stories. Author pages also list the author's favourites, which we
+/// leave out.
+fn listing(page: &Url, text: &str) -> Listing {
+    let document = nipper::Document::from(text);
+    let selector = if document.select("div.mystories").exists() {
+        "div.mystories a.stitle"
+    } else {
+        "div.z-list a.stitle"
+    };
+    let mut seen = HashSet::new();
+    let urls = document
+        .select(selector)
+        .iter()
+        .filter_map(|link| resolve::link(page, &link.attr("href")?))
+        .filter_map(|url| story_url(&url, 1))
+        .filter(|url| seen.insert(url.clone()))
+        .map(String::from)
+        .collect();
+    let next = document
+        .select("center a")
+        .iter()
+        .find(|link| link.text().trim().starts_with("Next"))
+        .and_then(|link| resolve::link(page, &link.attr("href")?));
+    Listing { urls, next }
+}
+
+/// The url of a chapter of the story a url points into, if it does.
+fn story_url(url: &Url, chapter: usize) -> Option<Url> {
+    let segments: Vec<_> = url.path_segments()?.filter(|x| !x.is_empty()).collect();
+    match segments[..] {
+        ["s", id, ..] if id.chars().all(|u| u.is_ascii_digit()) => {
+            let path = match segments.get(3) {
+                Some(slug) => format!("/s/{}/{}/{}", id, chapter, slug),
+                None => format!("/s/{}/{}/", id, chapter),
+            };
+            url.join(&path).ok()
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,164 @@
+//! Fictionmania: stories are known by a number (`storyID`), whichever of their pages a url
+//! points to: the details (`/stories/readinfo.html?storyID=<id>`), the text to read online or
+//! the file to download. Any other page, e.g. an author's stories or the newest stories, is
+//! a listing of them.
+//!
+//! Stories are read online where the details page offers it, as html or as plain text, and
+//! otherwise from the download, which is either. A story with none of these is reported as
+//! `Error::NoStory`.
+
+use std::{collections::HashSet, fmt::Write};
+
+use super::prelude::*;
+use crate::{document::text::to_html_fragment, error::Error};
+
+pub struct BuildFictionmaniaAdapter;
+
+impl BuildAdapter for BuildFictionmaniaAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(FictionmaniaAdapter::new(options))
+    }
+}
+
+pub struct FictionmaniaAdapter {
+    http: Http,
+    story_id: Regex,
+}
+
+impl FictionmaniaAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self {
+            http: options.http,
+            story_id: Regex::new(r"(?i)storyID=(\d+)").unwrap(),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for FictionmaniaAdapter {
+    fn name(&self) -> &'static str {
+        "fictionmania"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let page = Url::parse(url)?;
+        if let Some(id) = story_id(&page) {
+            let info = info_url(&page, &id)?;
+            return Ok(DirectoryStream::new(Some(info.into()), Metadata::new()));
+        }
+
+        // Listings can run to thousands of stories, which is more than we want to build a
+        // document tree for; every story is linked by its number, which is all we need.
+        let text = self.http.text(url).await?;
+        let mut seen = HashSet::new();
+        let mut stories = Vec::new();
+        for captures in self.story_id.captures_iter(&text) {
+            let id = &captures[1];
+            if seen.insert(id.to_owned()) {
+                stories.push(String::from(info_url(&page, id)?));
+            }
+        }
+        Ok(DirectoryStream::new(stories, Metadata::new()))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let page = Url::parse(context.url())?;
+        let text = self.http.text(page.as_str()).await?;
+
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+        let source = {
+            let document = nipper::Document::from(&text);
+
+            // Details are a table of labelled rows.
+            for row in document.select("table.storyinfo tr").iter() {
+                let label = row.select("th").text();
+                let value = row.select("td").text();
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                let key = match label.trim().trim_end_matches(':') {
+                    "Title" => Meta::Title,
+                    "Author" => Meta::Author,
+                    "Date" => Meta::PublicationDate,
+                    "Keywords" => Meta::Tags,
+                    "Synopsis" => Meta::Summary,
+                    "Age" => Meta::Rating,
+                    "Size" => Meta::Other("size".into()),
+                    _ => continue,
+                };
+                meta.insert(key.clone(), key.parse(value));
+            }
+
+            let link = |kind: &str| {
+                document
+                    .select(&format!("a[href*='{}']", kind))
+                    .attr("href")
+                    .and_then(|href| resolve::link(&page, &href))
+            };
+            link("readhtmlstory")
+                .map(Source::Html)
+                .or_else(|| link("readtextstory").map(Source::Text))
+                .or_else(|| link("download").map(Source::Download))
+        };
+
+        let content = match source.ok_or_else(|| Error::NoStory(page.to_string()))? {
+            Source::Html(url) => {
+                let text = self.http.text(url.as_str()).await?;
+                let document = nipper::Document::from(&text);
+                document.select("div#storytext").html().trim().to_owned()
+            }
+            Source::Text(url) => {
+                let text = self.http.text(url.as_str()).await?;
+                let document = nipper::Document::from(&text);
+                to_html_fragment(&document.select("pre").first().text())
+            }
+            // Downloads are as uploaded, as plain text or as a page of their own.
+            Source::Download(url) => {
+                let response = self.http.get(url.as_str()).await?;
+                if response.is_html() {
+                    body_html(&response.body)
+                } else {
+                    to_html_fragment(&response.body)
+                }
+            }
+        };
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        writeln!(buf, "{}", content.trim_end()).unwrap();
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+/// Where a story's text is to be had, in order of preference.
+enum Source {
+    Html(Url),
+    Text(Url),
+    Download(Url),
+}
+
+/// The number of the story a url points to, if it does.
+fn story_id(url: &Url) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key.eq_ignore_ascii_case("storyid"))
+        .map(|(_, id)| id.into_owned())
+        .filter(|id| !id.is_empty() && id.chars().all(|u| u.is_ascii_digit()))
+}
+
+/// What a page holds, without its `<body>` tag.
+fn body_html(text: &str) -> String {
+    let body = nipper::Document::from(text).select("body").html();
+    let inner = body.split_once('>').map_or("", |(_, inner)| inner);
+    inner
+        .strip_suffix("</body>")
+        .unwrap_or(inner)
+        .trim()
+        .to_owned()
+}
+
+fn info_url(page: &Url, id: &str) -> Result<Url> {
+    Ok(page.join(&format!("/stories/readinfo.html?storyID={}", id))?)
+}
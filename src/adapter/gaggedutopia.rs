@@ -3,55 +3,58 @@ use super::prelude::*;
 pub struct BuildGaggedUtopiaAdapter;
 
 impl BuildAdapter for BuildGaggedUtopiaAdapter {
-    fn build(&self) -> Box<dyn Adapter + 'static> {
-        Box::new(GaggedUtopiaAdapter::new())
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(GaggedUtopiaAdapter::new(options))
     }
 }
 
 pub struct GaggedUtopiaAdapter {
-    client: Client,
+    http: Http,
     title: Regex,
 }
 
 impl GaggedUtopiaAdapter {
-    fn new() -> Self {
+    fn new(options: AdapterOptions) -> Self {
         Self {
-            client: Client::builder().user_agent(USER_AGENT).build().unwrap(),
+            http: options.http,
             title: Regex::new(r#"(.+) ::"#).unwrap(),
         }
     }
 }
 
-impl Adapter for GaggedUtopiaAdapter {
-    fn directory(&self, url: &str) -> Result<DirectoryUrls> {
-        let text = self.client.get(url).send()?.text()?;
+#[async_trait]
+impl AsyncAdapter for GaggedUtopiaAdapter {
+    fn name(&self) -> &'static str {
+        "gaggedutopia"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let text = self.http.text(url).await?;
         let document = nipper::Document::from(&text);
 
-        let mut meta = HashMap::new();
+        let mut meta = Metadata::new();
         if let Some(author) = try_get_author_from_url(url) {
             meta.insert(Meta::Author, author);
         }
 
+        let page = Url::parse(url)?;
         let link_pattern = Regex::new(r#"/code/show_story.asp/recid/\d+"#).unwrap();
         let links = document
             .select("tr > td > b > a")
             .iter()
             .filter_map(|cx| cx.attr("href"))
             .filter(|x| link_pattern.is_match(x))
-            .map(RelativeUrl);
+            .filter_map(|href| resolve::link(&page, &href));
 
-        Ok(DirectoryUrls {
-            urls: links.map(|link| link.url()).collect(),
-            page: None,
-            meta,
-        })
+        Ok(DirectoryStream::new(links.map(String::from), meta))
     }
 
-    fn download(&self, context: DocumentUrl) -> Result<Document> {
-        let text = self.client.get(&context.url).send()?.text()?;
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let text = self.http.text(&context.url).await?;
         let document = nipper::Document::from(&text);
 
-        let mut meta = context.meta;
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(&context.url));
         if let Some(title) = self
             .title
             .captures(try_get_title(&document).as_ref())
@@ -61,7 +64,10 @@ impl Adapter for GaggedUtopiaAdapter {
             meta.insert(Meta::Title, title);
         }
 
-        Ok(Document { meta, text })
+        Ok(Document {
+            meta: context.merge(meta),
+            text,
+        })
     }
 }
 
@@ -77,11 +83,3 @@ fn try_get_author_from_url(url: &str) -> Option<String> {
 fn try_get_title(document: &nipper::Document) -> impl AsRef<str> {
     document.select("head > title").text()
 }
-
-struct RelativeUrl<T>(T);
-
-impl<T: AsRef<str>> RelativeUrl<T> {
-    fn url(&self) -> String {
-        format!("https://www.utopiastories.com{}", self.0.as_ref())
-    }
-}
@@ -0,0 +1,451 @@
+//! The http layer shared by all adapters.
+//!
+//! Requests go to the network, optionally recording each response to a fixture directory, or
+//! are answered from a fixture directory without touching the network at all. Recorded
+//! fixtures are what the adapter conformance tests run against.
+//!
+//! Requests for an overridden host are sent to another origin instead, e.g. a mirror or a
+//! local test server; the url seen by adapters and recorded in fixtures stays the same.
+//!
+//! Every exchange with a site can also be captured to a WARC file, which can later be replayed
+//! in the same way as fixtures.
+//!
+//! An http layer can also be pinned to an archived snapshot, in which case every url is
+//! requested from the archive instead.
+//!
+//! Cookies are kept for as long as the http layer and its clones are, so a session started
+//! with `post_form`, e.g. by logging in, carries over to later requests.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::{header, Client};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::prelude::USER_AGENT;
+use crate::{error::Error, Result};
+
+pub mod warc;
+
+/// A response, as retrieved or as recorded in a fixture.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Response {
+    #[serde(default = "get", skip_serializing_if = "is_get")]
+    pub method: String,
+    /// The url that was requested.
+    pub url: String,
+    #[serde(default = "ok")]
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+/// Text found only in challenge pages. Cloudflare also adds scripts under
+/// `/cdn-cgi/challenge-platform/` to ordinary pages, so those are not enough to go by.
+const CHALLENGE_MARKERS: &[&str] = &[
+    "<title>Just a moment...</title>",
+    "window._cf_chl_opt",
+    "id=\"challenge-form\"",
+    "<title>Attention Required! | Cloudflare</title>",
+];
+
+fn ok() -> u16 {
+    200
+}
+
+fn get() -> String {
+    "GET".into()
+}
+
+fn is_get(method: &str) -> bool {
+    method == "GET"
+}
+
+impl Response {
+    /// The key a response is replayed by: its url, preceded by the method for anything other
+    /// than a get.
+    fn key(&self) -> String {
+        request_key(&self.method, &self.url)
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.content_type
+            .as_deref()
+            .map(|value| value.contains("html"))
+            .unwrap_or_default()
+    }
+
+    /// Whether this is a page a site puts in front of suspected bots, e.g. Cloudflare's
+    /// "Just a moment...", rather than the page that was asked for.
+    pub fn is_challenge(&self) -> bool {
+        self.is_html()
+            && CHALLENGE_MARKERS
+                .iter()
+                .any(|marker| self.body.contains(marker))
+    }
+}
+
+#[derive(Clone)]
+pub struct Http {
+    client: Client,
+    mode: Arc<Mode>,
+    overrides: Arc<HashMap<String, Url>>,
+    /// Prefix of the archived copy of every url, e.g. `https://web.archive.org/web/2020id_/`.
+    snapshot: Option<Arc<str>>,
+    warc: Option<Arc<Mutex<warc::Writer>>>,
+}
+
+enum Mode {
+    Live,
+    Record(PathBuf),
+    Replay(HashMap<String, Response>),
+}
+
+/// How long a request may take, unless set otherwise with `Http::with_timeout`.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+/// How long connecting may take, out of the time a request may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn client(timeout: Duration) -> Client {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .cookie_store(true)
+        .timeout(timeout)
+        .connect_timeout(CONNECT_TIMEOUT.min(timeout))
+        .build()
+        .unwrap()
+}
+
+impl Http {
+    pub fn new() -> Self {
+        Self {
+            client: client(TIMEOUT),
+            mode: Arc::new(Mode::Live),
+            overrides: Default::default(),
+            snapshot: None,
+            warc: None,
+        }
+    }
+
+    /// Retrieve responses from the network, saving each of them to `dir`.
+    pub fn record(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            mode: Arc::new(Mode::Record(dir)),
+            ..Self::new()
+        })
+    }
+
+    /// Answer requests only from the fixtures saved in `dir`.
+    pub fn replay(dir: &Path) -> Result<Self> {
+        let mut responses = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                let response: Response = serde_json::from_slice(&fs::read(&path)?)?;
+                responses.insert(response.key(), response);
+            }
+        }
+        Ok(Self {
+            mode: Arc::new(Mode::Replay(responses)),
+            ..Self::new()
+        })
+    }
+
+    /// Answer requests only from the responses captured in a WARC file.
+    pub fn from_warc(path: &Path) -> Result<Self> {
+        let responses = warc::read(path)?
+            .into_iter()
+            .map(|response| (response.key(), response))
+            .collect();
+        Ok(Self {
+            mode: Arc::new(Mode::Replay(responses)),
+            ..Self::new()
+        })
+    }
+
+    /// Capture every request and response to a WARC file at `path`.
+    pub fn with_warc(mut self, path: &Path) -> Result<Self> {
+        self.warc = Some(Arc::new(Mutex::new(warc::Writer::create(path)?)));
+        Ok(self)
+    }
+
+    /// Fail requests that take longer than `timeout`, in place of `TIMEOUT`.
+    ///
+    /// This starts a new session, so call it before logging in anywhere.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = client(timeout);
+        self
+    }
+
+    /// Send requests for each host to the origin given for it, e.g. `http://127.0.0.1:8080`,
+    /// on top of the overrides already set.
+    pub fn with_overrides(mut self, overrides: HashMap<String, Url>) -> Self {
+        if !overrides.is_empty() {
+            Arc::make_mut(&mut self.overrides).extend(overrides);
+        }
+        self
+    }
+
+    /// Request every url from an archive instead, by prefixing it with `prefix`.
+    pub fn archived(&self, prefix: impl Into<Arc<str>>) -> Self {
+        Self {
+            snapshot: Some(prefix.into()),
+            ..self.clone()
+        }
+    }
+
+    /// The underlying client, for requests other than a plain get.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Retrieve a page; responses other than a success are returned as `Error::Status`, and
+    /// challenge pages as `Error::Challenge`.
+    pub async fn get(&self, url: &str) -> Result<Response> {
+        let url = match &self.snapshot {
+            Some(prefix) => Cow::Owned(format!("{}{}", prefix, url)),
+            None => Cow::Borrowed(url),
+        };
+        self.request(url.as_ref(), None).await
+    }
+
+    /// Submit a form, e.g. to log in.
+    ///
+    /// Forms may hold credentials, so they are neither saved with recorded fixtures nor
+    /// captured to WARC files; replayed responses are matched on the url alone.
+    pub async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<Response> {
+        self.request(url, Some(form)).await
+    }
+
+    async fn request(&self, url: &str, form: Option<&[(&str, &str)]>) -> Result<Response> {
+        let method = if form.is_some() { "POST" } else { "GET" };
+        let response = match &*self.mode {
+            Mode::Live => self.fetch(url, form).await?,
+            Mode::Record(dir) => {
+                let response = self.fetch(url, form).await?;
+                let file = fs::File::create(dir.join(fixture_name(&response.key())))?;
+                serde_json::to_writer_pretty(file, &response)?;
+                response
+            }
+            Mode::Replay(responses) => responses
+                .get(&request_key(method, url))
+                .cloned()
+                .ok_or_else(|| Error::NotRecorded(request_key(method, url)))?,
+        };
+
+        if response.is_challenge() {
+            Err(Error::Challenge(response.url))
+        } else if (200..300).contains(&response.status) {
+            Ok(response)
+        } else {
+            Err(Error::Status(response.status, response.url))
+        }
+    }
+
+    /// Retrieve the body of a page.
+    pub async fn text(&self, url: &str) -> Result<String> {
+        Ok(self.get(url).await?.body)
+    }
+
+    /// Retrieve a JSON document, e.g. from a site's API; see `adapter::json` for reading it.
+    pub async fn json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        Ok(serde_json::from_str(&self.text(url).await?)?)
+    }
+
+    async fn fetch(&self, url: &str, form: Option<&[(&str, &str)]>) -> Result<Response> {
+        let route = self.route(url);
+        let (method, request) = match form {
+            Some(form) => ("POST", self.client.post(&*route).form(form)),
+            None => ("GET", self.client.get(&*route)),
+        };
+        let response = request.send().await?;
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+
+        let mut head = format!(
+            "{:?} {} {}\r\n",
+            response.version(),
+            status.as_u16(),
+            status.canonical_reason().unwrap_or_default()
+        );
+        for (name, value) in response.headers() {
+            if name != header::TRANSFER_ENCODING {
+                head += &format!(
+                    "{}: {}\r\n",
+                    name,
+                    String::from_utf8_lossy(value.as_bytes())
+                );
+            }
+        }
+        head += "\r\n";
+
+        let body = response.bytes().await?;
+        if let Some(warc) = &self.warc {
+            warc.lock().unwrap().write_exchange(&warc::Exchange {
+                url,
+                request: request_head(method, url),
+                head,
+                body: &body,
+            })?;
+        }
+
+        Ok(Response {
+            method: method.into(),
+            url: url.into(),
+            status: status.as_u16(),
+            body: decode(&body, content_type.as_deref()),
+            content_type,
+        })
+    }
+}
+
+impl Http {
+    /// The url a request is actually sent to, taking host overrides into account.
+    fn route<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        let mut target = match Url::parse(url) {
+            Ok(target) => target,
+            Err(_) => return Cow::Borrowed(url),
+        };
+        let origin = match target.host_str().and_then(|host| self.overrides.get(host)) {
+            Some(origin) => origin,
+            None => return Cow::Borrowed(url),
+        };
+
+        // These only fail for urls that cannot be http(s) in the first place.
+        let _ = target.set_scheme(origin.scheme());
+        let _ = target.set_host(origin.host_str());
+        let _ = target.set_port(origin.port());
+        Cow::Owned(target.into())
+    }
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn request_key(method: &str, url: &str) -> String {
+    if is_get(method) {
+        url.into()
+    } else {
+        format!("{} {}", method, url)
+    }
+}
+
+/// The request line and headers of a request, as recorded in WARC files.
+fn request_head(method: &str, url: &str) -> String {
+    let url = Url::parse(url).ok();
+    let host = url.as_ref().and_then(Url::host_str).unwrap_or_default();
+    let path = url
+        .as_ref()
+        .map(|url| match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        })
+        .unwrap_or_else(|| "/".into());
+    format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}\r\nAccept: */*\r\n\r\n",
+        method, path, host, USER_AGENT
+    )
+}
+
+/// Decode a body in the charset named by its content type, defaulting to utf-8.
+fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(|value| {
+            value
+                .split(';')
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        })
+        .and_then(|(_, charset)| {
+            encoding_rs::Encoding::for_label(charset.trim().trim_matches('"').as_bytes())
+        })
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(body).0.into_owned()
+}
+
+/// A readable file name for the fixture of a url, made unique by a hash of the url.
+///
+/// Requests other than a get are named by their key, e.g. `POST https://...`.
+fn fixture_name(url: &str) -> String {
+    let slug: String = url
+        .split("://")
+        .last()
+        .unwrap_or(url)
+        .chars()
+        .map(|u| if u.is_ascii_alphanumeric() { u } else { '-' })
+        .take(80)
+        .collect();
+    let digest = Sha256::digest(url.as_bytes());
+    let hash: String = digest[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}-{}.json", slug.trim_matches('-'), hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Http;
+
+    #[test]
+    fn route() {
+        let overrides = HashMap::from([(
+            "www.asstr.org".to_string(),
+            "http://127.0.0.1:8080".parse().unwrap(),
+        )]);
+        let http = Http::new().with_overrides(overrides);
+        assert_eq!(
+            http.route("https://www.asstr.org/files/Authors/?page=2"),
+            "http://127.0.0.1:8080/files/Authors/?page=2"
+        );
+        assert_eq!(
+            http.route("https://www.sexstories.com/story/1"),
+            "https://www.sexstories.com/story/1"
+        );
+
+        // Later overrides add to earlier ones.
+        let http = http.with_overrides(HashMap::from([(
+            "www.sexstories.com".to_string(),
+            "http://127.0.0.1:8081".parse().unwrap(),
+        )]));
+        assert_eq!(
+            http.route("https://www.asstr.org/files/"),
+            "http://127.0.0.1:8080/files/"
+        );
+        assert_eq!(
+            http.route("https://www.sexstories.com/story/1"),
+            "http://127.0.0.1:8081/story/1"
+        );
+    }
+
+    #[test]
+    fn fixture_name() {
+        let name = super::fixture_name("https://www.asstr.org/files/Authors/Somebody/story.txt");
+        assert!(name.starts_with("www-asstr-org-files-Authors-Somebody-story-txt-"));
+        assert!(name.ends_with(".json"));
+        assert_ne!(
+            name,
+            super::fixture_name("https://www.asstr.org/files/Authors/Somebody/story-txt")
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Reading and writing WARC/1.1 files of the http exchanges adapters make.
+//!
+//! Each record is written as its own gzip member, as is usual for `.warc.gz` files. Bodies are
+//! recorded as received, except that any transfer encoding has already been undone, so the
+//! `Transfer-Encoding` header is left out.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use chrono::Utc;
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use uuid::Uuid;
+
+use super::{decode, Response};
+use crate::Result;
+
+/// One request and the response to it.
+pub struct Exchange<'a> {
+    pub url: &'a str,
+    /// The request line and headers.
+    pub request: String,
+    /// The status line and headers.
+    pub head: String,
+    pub body: &'a [u8],
+}
+
+pub struct Writer {
+    file: File,
+}
+
+impl Writer {
+    /// Create a WARC file, starting with a `warcinfo` record.
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut writer = Self {
+            file: File::create(path)?,
+        };
+        let info = format!(
+            "software: klit/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        writer.write_record(
+            "warcinfo",
+            &record_id(),
+            &[("Content-Type", "application/warc-fields")],
+            info.as_bytes(),
+        )?;
+        Ok(writer)
+    }
+
+    pub fn write_exchange(&mut self, exchange: &Exchange) -> Result<()> {
+        let request_id = record_id();
+        let response_id = record_id();
+
+        let mut response = exchange.head.clone().into_bytes();
+        response.extend_from_slice(exchange.body);
+        self.write_record(
+            "response",
+            &response_id,
+            &[
+                ("WARC-Target-URI", exchange.url),
+                ("Content-Type", "application/http; msgtype=response"),
+            ],
+            &response,
+        )?;
+        self.write_record(
+            "request",
+            &request_id,
+            &[
+                ("WARC-Target-URI", exchange.url),
+                ("WARC-Concurrent-To", &response_id),
+                ("Content-Type", "application/http; msgtype=request"),
+            ],
+            exchange.request.as_bytes(),
+        )
+    }
+
+    fn write_record(
+        &mut self,
+        kind: &str,
+        id: &str,
+        fields: &[(&str, &str)],
+        block: &[u8],
+    ) -> Result<()> {
+        let mut record = GzEncoder::new(Vec::new(), Compression::default());
+        write!(
+            record,
+            "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+            kind,
+            id,
+            Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+        )?;
+        for (name, value) in fields {
+            write!(record, "{}: {}\r\n", name, value)?;
+        }
+        write!(record, "Content-Length: {}\r\n\r\n", block.len())?;
+        record.write_all(block)?;
+        record.write_all(b"\r\n\r\n")?;
+
+        self.file.write_all(&record.finish()?)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+/// Read the responses recorded in a WARC file, compressed or not.
+pub fn read(path: &Path) -> Result<Vec<Response>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn BufRead> = if file.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    };
+    read_records(reader)
+}
+
+fn read_records(mut reader: impl BufRead) -> Result<Vec<Response>> {
+    // Responses by record id, and the methods of the requests made for them.
+    let mut responses = Vec::new();
+    let mut methods = HashMap::new();
+    let mut line = String::new();
+    loop {
+        // Records are separated by blank lines.
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        if !line.starts_with("WARC/") {
+            continue;
+        }
+
+        let mut fields = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                fields.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+            }
+        }
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        let length = field("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        let mut block = vec![0; length];
+        reader.read_exact(&mut block)?;
+
+        match field("warc-type") {
+            Some("response") => {
+                if let Some(response) = field("warc-target-uri").and_then(|url| parse(url, &block))
+                {
+                    responses.push((field("warc-record-id").map(str::to_owned), response));
+                }
+            }
+            Some("request") => {
+                let method = block
+                    .split(|byte| *byte == b' ')
+                    .next()
+                    .map(|method| String::from_utf8_lossy(method).into_owned());
+                if let (Some(id), Some(method)) = (field("warc-concurrent-to"), method) {
+                    methods.insert(id.to_owned(), method);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(responses
+        .into_iter()
+        .map(|(id, mut response)| {
+            if let Some(method) = id.and_then(|id| methods.remove(&id)) {
+                response.method = method;
+            }
+            response
+        })
+        .collect())
+}
+
+/// Parse the http response held in a response record.
+fn parse(url: &str, block: &[u8]) -> Option<Response> {
+    let split = block.windows(4).position(|window| window == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&block[..split]);
+    let body = &block[split + 4..];
+
+    let mut lines = head.lines();
+    let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+    let content_type = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-type")
+            .then(|| value.trim().to_owned())
+    });
+
+    Some(Response {
+        method: "GET".into(),
+        url: url.into(),
+        status,
+        body: decode(body, content_type.as_deref()),
+        content_type,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Exchange, Writer};
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.warc.gz");
+
+        let mut writer = Writer::create(&path).unwrap();
+        for (url, head, body) in [
+            (
+                "https://www.asstr.org/files/Authors/Somebody/",
+                "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n\r\n",
+                &b"<a href=\"story.txt\">story.txt</a>"[..],
+            ),
+            (
+                "https://www.asstr.org/files/Authors/Somebody/story.txt",
+                "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=iso-8859-1\r\n\r\n",
+                &b"Caf\xe9\r\n\r\nThe end."[..],
+            ),
+            (
+                "https://www.asstr.org/files/Authors/Somebody/gone.txt",
+                "HTTP/1.1 404 Not Found\r\n\r\n",
+                &b""[..],
+            ),
+            (
+                "https://www.asstr.org/login",
+                "HTTP/1.1 200 OK\r\n\r\n",
+                &b"welcome"[..],
+            ),
+        ] {
+            let method = if url.ends_with("login") {
+                "POST"
+            } else {
+                "GET"
+            };
+            writer
+                .write_exchange(&Exchange {
+                    url,
+                    request: format!("{} {} HTTP/1.1\r\n\r\n", method, url),
+                    head: head.into(),
+                    body,
+                })
+                .unwrap();
+        }
+        drop(writer);
+
+        let responses = super::read(&path).unwrap();
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0].body, "<a href=\"story.txt\">story.txt</a>");
+        assert!(responses[0].is_html());
+        assert_eq!(responses[1].body, "Caf\u{e9}\r\n\r\nThe end.");
+        assert_eq!(responses[2].status, 404);
+        assert_eq!(responses[2].content_type, None);
+        assert_eq!(responses[2].method, "GET");
+        assert_eq!(responses[3].method, "POST");
+    }
+}
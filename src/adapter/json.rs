@@ -0,0 +1,107 @@
+//! Helpers for adapters that read a site's JSON API rather than its pages.
+//!
+//! APIs change under us: fields come and go, or change type, e.g. an id sent as a number by
+//! one endpoint and as a string by another. Models should give every field a default and read
+//! it with one of these helpers, so that a surprise in one field costs that field rather than
+//! the whole document:
+//!
+//! ```ignore
+//! #[derive(Default, Deserialize)]
+//! #[serde(default)]
+//! struct Story {
+//!     #[serde(deserialize_with = "json::string")]
+//!     id: String,
+//!     #[serde(deserialize_with = "json::lenient")]
+//!     title: String,
+//!     #[serde(deserialize_with = "json::list")]
+//!     tags: Vec<String>,
+//! }
+//! ```
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::Value as Json;
+
+/// Read a value, falling back to its default if it is null or of another type.
+pub fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = Json::deserialize(deserializer)?;
+    Ok(T::deserialize(value).unwrap_or_default())
+}
+
+/// Read a list, keeping the items that can be read and skipping the rest; anything other
+/// than a list is read as a list of one.
+pub fn list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let items = match Json::deserialize(deserializer)? {
+        Json::Array(items) => items,
+        Json::Null => Vec::new(),
+        item => vec![item],
+    };
+    Ok(items
+        .into_iter()
+        .filter_map(|item| T::deserialize(item).ok())
+        .collect())
+}
+
+/// Read text that may also be sent as a number or a boolean, e.g. an id.
+pub fn string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Json::deserialize(deserializer)? {
+        Json::String(text) => text,
+        Json::Number(number) => number.to_string(),
+        Json::Bool(value) => value.to_string(),
+        _ => String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    #[serde(default)]
+    struct Story {
+        #[serde(deserialize_with = "super::string")]
+        id: String,
+        #[serde(deserialize_with = "super::lenient")]
+        title: String,
+        #[serde(deserialize_with = "super::lenient")]
+        parts: Option<u32>,
+        #[serde(deserialize_with = "super::list")]
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn drift() {
+        let story = |json| serde_json::from_str::<Story>(json).unwrap();
+        let expected = Story {
+            id: "12".into(),
+            title: "Title".into(),
+            parts: Some(3),
+            tags: vec!["a".into(), "b".into()],
+        };
+        assert_eq!(
+            story(r#"{"id": "12", "title": "Title", "parts": 3, "tags": ["a", "b"]}"#),
+            expected
+        );
+        assert_eq!(
+            story(r#"{"id": 12, "title": "Title", "parts": 3, "tags": ["a", 1, "b"], "new": {}}"#),
+            expected
+        );
+        assert_eq!(
+            story(r#"{"title": null, "parts": "three", "tags": "a"}"#),
+            Story {
+                tags: vec!["a".into()],
+                ..Story::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,312 @@
+//! Literotica: stories (`/s/<slug>`), series (`/series/se/<id>`) and author submissions
+//! (`/authors/<name>/works/stories`).
+//!
+//! Stories are split into pages (`?page=N`), which are joined into one document. Series are
+//! retrieved as one document per part, or as a single document holding every part when the
+//! `literotica.series` setting is `merge`. A story of more than `MAX_PAGES` pages is cut
+//! short, and says so in its `truncated` metadata.
+
+use std::{collections::HashSet, fmt::Write};
+
+use super::prelude::*;
+use crate::document::text::escape;
+
+/// The most pages we retrieve of one story; even the longest run to a few dozen.
+const MAX_PAGES: u32 = 100;
+
+pub struct BuildLiteroticaAdapter;
+
+impl BuildAdapter for BuildLiteroticaAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(LiteroticaAdapter::new(options))
+    }
+}
+
+pub struct LiteroticaAdapter {
+    http: Http,
+    merge_series: bool,
+}
+
+impl LiteroticaAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self {
+            merge_series: options.setting("literotica.series") == Some("merge"),
+            http: options.http,
+        }
+    }
+
+    /// Retrieve a story and every further page of it.
+    async fn story(&self, url: &Url) -> Result<Story> {
+        let text = self.http.text(url.as_str()).await?;
+        let (mut meta, pages, last) = {
+            let document = nipper::Document::from(&text);
+            (
+                story_meta(&document),
+                vec![select_content(&document)],
+                last_page(&document, url),
+            )
+        };
+
+        if last > MAX_PAGES {
+            meta.insert(
+                Meta::Other("truncated".into()),
+                format!("stopped after {} of {} pages", MAX_PAGES, last),
+            );
+        }
+
+        let mut story = Story { meta, pages };
+        for page in 2..=last.min(MAX_PAGES) {
+            let url = resolve::with_query(url, "page", &page.to_string());
+            let text = self.http.text(url.as_str()).await?;
+            story
+                .pages
+                .push(select_content(&nipper::Document::from(&text)));
+        }
+        Ok(story)
+    }
+
+    /// Retrieve every part of a series as a single document.
+    async fn series(&self, url: &Url) -> Result<(Metadata, String)> {
+        let text = self.http.text(url.as_str()).await?;
+        let (mut meta, parts) = {
+            let document = nipper::Document::from(&text);
+            (series_meta(&document), story_links(&document, url))
+        };
+
+        let mut buf = String::new();
+        for part in parts {
+            let story = self.story(&part).await?;
+            writeln!(
+                buf,
+                "<h2>{}</h2>",
+                escape(story.meta.text(&Meta::Title).unwrap_or_default())
+            )
+            .unwrap();
+            for page in &story.pages {
+                writeln!(buf, "{}", page).unwrap();
+            }
+
+            // Keep the tags and categories of every part, and whether any was cut short.
+            let mut part_meta = Metadata::new();
+            for key in [
+                Meta::Tags,
+                Meta::Categories,
+                Meta::Other("truncated".into()),
+            ] {
+                if let Some(value) = story.meta.get(&key) {
+                    part_meta.insert(key, value.clone());
+                }
+            }
+            meta.merge(part_meta);
+        }
+        Ok((meta, buf))
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for LiteroticaAdapter {
+    fn name(&self) -> &'static str {
+        "literotica"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let page = Url::parse(url)?;
+        if page.path().starts_with("/s/") {
+            return Ok(DirectoryStream::new(
+                Some(story_url(&page).into()),
+                Metadata::new(),
+            ));
+        }
+
+        let text = self.http.text(url).await?;
+        if page.path().starts_with("/series/") {
+            let (meta, parts) = {
+                let document = nipper::Document::from(&text);
+                (series_meta(&document), story_links(&document, &page))
+            };
+            if self.merge_series {
+                return Ok(DirectoryStream::new(Some(page.into()), meta));
+            }
+
+            let mut directory = DirectoryStream::new(parts.iter().map(Url::to_string), meta);
+            for (idx, part) in parts.into_iter().enumerate() {
+                let mut meta = Metadata::new();
+                meta.insert(Meta::SeriesIndex, Value::Integer(idx as i64 + 1));
+                directory = directory.with_document_meta(part, meta);
+            }
+            return Ok(directory);
+        }
+
+        // Anything else is taken to be a listing of an author's submissions.
+        let mut meta = Metadata::new();
+        let author = nipper::Document::from(&text).select("h1").first().text();
+        if !author.trim().is_empty() {
+            meta.insert(Meta::Author, author.trim());
+        }
+        let first = listing(&page, &text);
+        let paging = LinkPaging::new(self.http.clone(), page, first.next, |page, text| {
+            Ok(listing(page, text))
+        });
+        Ok(DirectoryStream::new(first.urls, meta).with_paging(paging))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let url = Url::parse(context.url())?;
+        let (mut meta, content) = if url.path().starts_with("/series/") {
+            self.series(&url).await?
+        } else {
+            let story = self.story(&url).await?;
+            (story.meta, story.pages.join("\n"))
+        };
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        writeln!(buf, "{}", content).unwrap();
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+/// A story with the content of each of its pages.
+struct Story {
+    meta: Metadata,
+    pages: Vec<String>,
+}
+
+/// One page of an author's submissions.
+fn listing(page: &Url, text: &str) -> Listing {
+    let document = nipper::Document::from(text);
+
+    // Listings link to their next page as `?page=N`, like stories do.
+    let current = page_number(page).unwrap_or(1);
+    let next = document
+        .select("a[href*='page=']")
+        .iter()
+        .filter_map(|link| resolve::link(page, &link.attr("href")?))
+        .find(|url| page_number(url) == Some(current + 1));
+
+    Listing {
+        urls: story_links(&document, page)
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        next,
+    }
+}
+
+fn story_meta(document: &nipper::Document) -> Metadata {
+    let mut meta = Metadata::new();
+    let title = document.select("h1").first().text();
+    if !title.trim().is_empty() {
+        meta.insert(Meta::Title, title.trim());
+    }
+    if let Some(author) = first_text(document, "a[href*='/authors/'], a[href*='memberpage.php']") {
+        meta.insert(Meta::Author, author);
+    }
+    if let Some(summary) = document
+        .select("meta[name='description']")
+        .attr("content")
+        .filter(|summary| !summary.trim().is_empty())
+    {
+        meta.insert(Meta::Summary, summary.trim());
+    }
+    if let Some(series) = first_text(document, "a[href*='/series/se/']") {
+        meta.insert(Meta::Series, series);
+    }
+
+    // The category is linked from the breadcrumbs, the tags from below the story.
+    if let Some(category) = first_text(document, "a[href*='/c/']") {
+        meta.insert(Meta::Categories, Value::list([category]));
+    }
+    let tags: Vec<_> = document
+        .select("a[href*='/tags/'], a[href*='tags.literotica.com']")
+        .iter()
+        .map(|link| link.text().trim().to_owned())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    if !tags.is_empty() {
+        meta.insert(Meta::Tags, Value::list(tags));
+    }
+    meta
+}
+
+fn series_meta(document: &nipper::Document) -> Metadata {
+    let mut meta = Metadata::new();
+    let title = document.select("h1").first().text();
+    if !title.trim().is_empty() {
+        meta.insert(Meta::Title, title.trim());
+        meta.insert(Meta::Series, title.trim());
+    }
+    if let Some(author) = first_text(document, "a[href*='/authors/'], a[href*='memberpage.php']") {
+        meta.insert(Meta::Author, author);
+    }
+    meta
+}
+
+fn first_text(document: &nipper::Document, selector: &str) -> Option<String> {
+    document
+        .select(selector)
+        .iter()
+        .map(|x| x.text().trim().to_owned())
+        .find(|text| !text.is_empty())
+}
+
+/// The distinct stories linked from a page, in order.
+fn story_links(document: &nipper::Document, page: &Url) -> Vec<Url> {
+    let mut seen = HashSet::new();
+    document
+        .select("a[href*='/s/']")
+        .iter()
+        .filter_map(|link| resolve::link(page, &link.attr("href")?))
+        .filter(|url| url.path().starts_with("/s/"))
+        .map(|url| story_url(&url))
+        .filter(|url| seen.insert(url.clone()))
+        .collect()
+}
+
+/// A story's url without any page number, so that it starts from the first page.
+fn story_url(url: &Url) -> Url {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != "page")
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+
+    let mut url = url.clone();
+    if pairs.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url
+}
+
+fn page_number(url: &Url) -> Option<u32> {
+    url.query_pairs()
+        .find(|(name, _)| name == "page")
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// The number of the last page of a story, from the links to its other pages.
+///
+/// Pages also link to other stories, comments and listings with a page number of their own,
+/// so only links to the story itself count.
+fn last_page(document: &nipper::Document, story: &Url) -> u32 {
+    document
+        .select("a[href*='page=']")
+        .iter()
+        .filter_map(|link| resolve::link(story, &link.attr("href")?))
+        .filter(|url| url.host_str() == story.host_str() && url.path() == story.path())
+        .filter_map(|url| page_number(&url))
+        .max()
+        .unwrap_or(1)
+}
+
+fn select_content(document: &nipper::Document) -> String {
+    document
+        .select("div.aa_ht, div.b-story-body-x")
+        .html()
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,122 @@
+//! Lush Stories: stories (`/stories/<category>/<slug>`) and the stories of an author
+//! (`/profile/<name>`, or `/profile/<name>/stories`).
+
+use std::{collections::HashSet, fmt::Write};
+
+use super::prelude::*;
+
+pub struct BuildLushStoriesAdapter;
+
+impl BuildAdapter for BuildLushStoriesAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(LushStoriesAdapter::new(options))
+    }
+}
+
+pub struct LushStoriesAdapter {
+    http: Http,
+}
+
+impl LushStoriesAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self { http: options.http }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for LushStoriesAdapter {
+    fn name(&self) -> &'static str {
+        "lushstories"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let mut page = Url::parse(url)?;
+        if story_category(&page).is_some() {
+            return Ok(DirectoryStream::new(Some(url.to_owned()), Metadata::new()));
+        }
+
+        // An author's profile links to a page of their stories.
+        let segments: Vec<_> = page.path_segments().into_iter().flatten().collect();
+        if let ["profile", name] | ["profile", name, ""] = segments[..] {
+            page = page.join(&format!("/profile/{}/stories", name))?;
+        }
+
+        let text = self.http.text(page.as_str()).await?;
+        let document = nipper::Document::from(&text);
+        let mut meta = Metadata::new();
+        let author = document.select("div.profile-header h1").first().text();
+        if !author.trim().is_empty() {
+            meta.insert(Meta::Author, author.trim());
+        }
+
+        let mut seen = HashSet::new();
+        let stories = document
+            .select("div.story-list a.story-title")
+            .iter()
+            .filter_map(|link| resolve::link(&page, &link.attr("href")?))
+            .filter(|url| story_category(url).is_some())
+            .filter(|url| seen.insert(url.clone()));
+        Ok(DirectoryStream::new(stories.map(String::from), meta))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let page = Url::parse(context.url())?;
+        let text = self.http.text(page.as_str()).await?;
+        let document = nipper::Document::from(&text);
+
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+        let story = document.select("article.story");
+        let title = story.select("h1").first().text();
+        if !title.trim().is_empty() {
+            meta.insert(Meta::Title, title.trim());
+        }
+        let author = story.select("div.story-info a.author").first().text();
+        if !author.trim().is_empty() {
+            meta.insert(Meta::Author, author.trim());
+        }
+        if let Some(summary) = document
+            .select("meta[name='description']")
+            .attr("content")
+            .filter(|summary| !summary.trim().is_empty())
+        {
+            meta.insert(Meta::Summary, summary.trim());
+        }
+        if let Some(published) = story.select("div.story-info time").attr("datetime") {
+            meta.insert(Meta::PublicationDate, Value::date(&published));
+        }
+
+        // The category is named in the story's url, e.g. "first-time", and spelled out in a
+        // link back to it.
+        let category = story.select("div.story-info a.category").first().text();
+        let category = match category.trim() {
+            "" => story_category(&page).unwrap_or_default().replace('-', " "),
+            category => category.to_owned(),
+        };
+        meta.insert(Meta::Categories, Value::list([category]));
+
+        let tags: Vec<_> = story
+            .select("ul.story-tags a")
+            .iter()
+            .map(|tag| tag.text().to_string())
+            .collect();
+        if !tags.is_empty() {
+            meta.insert(Meta::Tags, Value::list(tags));
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        writeln!(buf, "{}", story.select("div.story-body").html().trim()).unwrap();
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+/// The category of the story a url points to, if it is one.
+fn story_category(url: &Url) -> Option<&str> {
+    let segments: Vec<_> = url.path_segments()?.filter(|x| !x.is_empty()).collect();
+    match segments[..] {
+        ["stories", category, _] if category != "tags" => Some(category),
+        _ => None,
+    }
+}
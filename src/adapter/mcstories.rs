@@ -0,0 +1,150 @@
+//! The Erotic Mind-Control Story Archive: stories (`/<Story>/index.html`, or any chapter of
+//! one), author pages (`/Authors/<Name>.html`) and listings such as `/WhatsNew.html`.
+//!
+//! A story's index page holds its metadata and a table of its chapters, which are joined into
+//! one document.
+
+use std::{collections::HashSet, fmt::Write};
+
+use super::prelude::*;
+use crate::document::text::escape;
+
+pub struct BuildMcStoriesAdapter;
+
+impl BuildAdapter for BuildMcStoriesAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(McStoriesAdapter::new(options))
+    }
+}
+
+pub struct McStoriesAdapter {
+    http: Http,
+    dateline: Regex,
+}
+
+impl McStoriesAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self {
+            http: options.http,
+            dateline: Regex::new(r"(Added|Updated) (\d{1,2} \w+ \d{4})").unwrap(),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for McStoriesAdapter {
+    fn name(&self) -> &'static str {
+        "mcstories"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let page = Url::parse(url)?;
+        if let Some(index) = story_index(&page) {
+            return Ok(DirectoryStream::new(Some(index.into()), Metadata::new()));
+        }
+
+        let text = self.http.text(url).await?;
+        let document = nipper::Document::from(&text);
+        let mut meta = Metadata::new();
+        if page.path().starts_with("/Authors/") {
+            let author = document.select("h3.title").first().text();
+            if !author.trim().is_empty() {
+                meta.insert(Meta::Author, author.trim());
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let stories = document
+            .select("a[href]")
+            .iter()
+            .filter_map(|link| resolve::link(&page, &link.attr("href")?))
+            .filter(|url| url.host_str() == page.host_str())
+            .filter_map(|url| story_index(&url))
+            .filter(|url| seen.insert(url.clone()));
+
+        Ok(DirectoryStream::new(stories.map(String::from), meta))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let index = Url::parse(context.url())?;
+        let text = self.http.text(index.as_str()).await?;
+
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+        let chapters: Vec<(String, Url)> = {
+            let document = nipper::Document::from(&text);
+            let title = document.select("h3.title").first().text();
+            if !title.trim().is_empty() {
+                meta.insert(Meta::Title, title.trim());
+            }
+            let author = document.select("h3.byline a").first().text();
+            if !author.trim().is_empty() {
+                meta.insert(Meta::Author, author.trim());
+            }
+            let synopsis = document.select("section.synopsis").text();
+            if !synopsis.trim().is_empty() {
+                meta.insert(Meta::Summary, synopsis.trim());
+            }
+
+            // Story codes, e.g. "mc mf ft".
+            let codes = document.select("div.codes").text();
+            if !codes.trim().is_empty() {
+                meta.insert(Meta::Tags, Value::list(codes.split_whitespace()));
+            }
+
+            for captures in self
+                .dateline
+                .captures_iter(&document.select("h3.dateline").text())
+            {
+                let key = match &captures[1] {
+                    "Added" => Meta::PublicationDate,
+                    _ => Meta::LastUpdated,
+                };
+                meta.insert(key, Value::date(&captures[2]));
+            }
+
+            // Stories in more than one chapter have a table of them, others a single link.
+            document
+                .select("table#index a, div.chapter a")
+                .iter()
+                .filter_map(|link| {
+                    let url = resolve::link(&index, &link.attr("href")?)?;
+                    Some((link.text().trim().to_owned(), url))
+                })
+                .collect()
+        };
+
+        let mut content = String::new();
+        let headings = chapters.len() > 1;
+        for (title, url) in chapters {
+            let text = self.http.text(url.as_str()).await?;
+            let document = nipper::Document::from(&text);
+            if headings {
+                writeln!(content, "<h2>{}</h2>", escape(&title)).unwrap();
+            }
+            for section in document.select("article#mcstories > section").iter() {
+                writeln!(content, "{}", section.html().trim()).unwrap();
+            }
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        buf.push_str(&content);
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+/// The index page of the story a url points into, if it does.
+///
+/// Each story has a directory of its own, holding its index and chapters; the site's own
+/// indexes are kept in a few directories of their own.
+fn story_index(url: &Url) -> Option<Url> {
+    let segments: Vec<_> = url.path_segments()?.collect();
+    match segments[..] {
+        [story, _] if !story.is_empty() && !["Authors", "Tags", "Titles"].contains(&story) => {
+            url.join("index.html").ok()
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,228 @@
+//! The Nifty Archive: stories are directories of plain-text parts (`story-1`, `story-2`, ...)
+//! under category directories, e.g. `/nifty/gay/college/<story>/`.
+//!
+//! A story directory, or any part in it, is retrieved as one document with a heading for each
+//! part. A category directory yields each story directory it lists.
+
+use std::cmp::Ordering;
+
+use super::prelude::*;
+use crate::{document::text, error::Error};
+
+pub struct BuildNiftyAdapter;
+
+impl BuildAdapter for BuildNiftyAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(NiftyAdapter::new(options))
+    }
+}
+
+pub struct NiftyAdapter {
+    http: Http,
+    part_number: Regex,
+}
+
+impl NiftyAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self {
+            http: options.http,
+            part_number: Regex::new(r"(?i)[\s\-:(]*(part|chapter|ch\.?)?\s*\d+\)?\s*$").unwrap(),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for NiftyAdapter {
+    fn name(&self) -> &'static str {
+        "nifty"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        // Parts are plain text; for a part we want the directory it is in.
+        let url = Url::parse(url)?;
+        let response = self.http.get(url.as_str()).await?;
+        let (page, text) = if response.is_html() {
+            (resolve::directory(&url), response.body)
+        } else {
+            let page = url.join(".")?;
+            let text = self.http.text(page.as_str()).await?;
+            (page, text)
+        };
+        let listing = Listing::parse(&page, &text);
+
+        // A directory holding parts is a story; otherwise its subdirectories are.
+        let stories = if listing.parts.is_empty() {
+            listing.directories
+        } else {
+            vec![page]
+        };
+        Ok(DirectoryStream::new(
+            stories.into_iter().map(String::from),
+            Metadata::new(),
+        ))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let page = Url::parse(context.url())?;
+        let mut parts = Listing::parse(&page, &self.http.text(page.as_str()).await?).parts;
+        if parts.is_empty() {
+            return Err(Error::NoStory(page.into()));
+        }
+        parts.sort_by(|a, b| natural_order(name(a), name(b)));
+
+        let mut texts = Vec::new();
+        for part in &parts {
+            texts.push(self.http.text(part.as_str()).await?);
+        }
+
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+        let categories: Vec<_> = page
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty())
+            .skip(1)
+            .collect();
+        if let Some((_, categories)) = categories.split_last() {
+            meta.insert(Meta::Categories, Value::list(categories));
+        }
+
+        let mut bodies = Vec::new();
+        for (idx, text) in texts.iter().enumerate() {
+            let (headers, body) = text::split_mail_headers(text);
+            let header = |name: &str| {
+                headers
+                    .iter()
+                    .find(|(header, _)| header == name)
+                    .map(|(_, value)| value.as_str())
+            };
+
+            if idx == 0 {
+                // Subjects number the parts of a story, e.g. "Some Story 3".
+                if let Some(subject) = header("subject") {
+                    let title = match texts.len() {
+                        1 => subject.into(),
+                        _ => self.part_number.replace(subject, ""),
+                    };
+                    meta.insert(Meta::Title, title.trim());
+                }
+                if let Some(from) = header("from") {
+                    // "Some Author <someone@example.com>", or just the address.
+                    let author = from.split('<').next().unwrap_or_default().trim();
+                    let author = if author.is_empty() { from } else { author };
+                    meta.insert(Meta::Author, author.trim_matches('"'));
+                }
+                if let Some(date) = header("date") {
+                    meta.insert(Meta::PublicationDate, Value::date(date));
+                }
+            } else if idx == texts.len() - 1 {
+                if let Some(date) = header("date") {
+                    meta.insert(Meta::LastUpdated, Value::date(date));
+                }
+            }
+            bodies.push((format!("Part {}", idx + 1), body));
+        }
+
+        // Without a subject to go by, the directory names the story.
+        if !meta.contains(&Meta::Title) {
+            let name = name(&page).replace(['-', '_'], " ");
+            meta.insert(Meta::Title, name.trim());
+        }
+
+        let meta = context.merge(meta);
+        let title = meta.text(&Meta::Title);
+        let text = match &bodies[..] {
+            [(_, body)] => text::to_html(body, title),
+            _ => text::parts_to_html(
+                bodies
+                    .iter()
+                    .map(|(heading, body)| (heading.as_str(), *body)),
+                title,
+            ),
+        };
+        Ok(Document { meta, text })
+    }
+}
+
+/// The entries of a directory listing.
+struct Listing {
+    parts: Vec<Url>,
+    directories: Vec<Url>,
+}
+
+impl Listing {
+    fn parse(page: &Url, text: &str) -> Self {
+        let document = nipper::Document::from(text);
+        let mut listing = Listing {
+            parts: Vec::new(),
+            directories: Vec::new(),
+        };
+
+        for url in document
+            .select("a[href]")
+            .iter()
+            .filter_map(|link| resolve::link(page, &link.attr("href")?))
+        {
+            // Only entries of this directory, not sort links, its parent or other pages.
+            let entry = match url.path().strip_prefix(page.path()) {
+                Some(entry) if !entry.is_empty() && url.query().is_none() => entry,
+                _ => continue,
+            };
+            let entries = match entry.strip_suffix('/') {
+                Some(directory) if !directory.contains('/') => &mut listing.directories,
+                None if !entry.contains('/') => &mut listing.parts,
+                _ => continue,
+            };
+            if !entries.contains(&url) {
+                entries.push(url);
+            }
+        }
+        listing
+    }
+}
+
+/// The last segment of a url's path, ignoring a trailing slash.
+fn name(url: &Url) -> &str {
+    url.path()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+}
+
+/// Compare names so that numbers within them are ordered by value: `story-2` before
+/// `story-10`.
+fn natural_order(a: &str, b: &str) -> Ordering {
+    let chunks = |name: &str| {
+        let mut chunks: Vec<(String, u64)> = Vec::new();
+        let mut chars = name.chars().peekable();
+        while let Some(u) = chars.next() {
+            let mut chunk = u.to_string();
+            let digits = u.is_ascii_digit();
+            while let Some(u) = chars.next_if(|u| u.is_ascii_digit() == digits) {
+                chunk.push(u);
+            }
+            if digits {
+                chunks.push((String::new(), chunk.parse().unwrap_or(u64::MAX)));
+            } else {
+                chunks.push((chunk, 0));
+            }
+        }
+        chunks
+    };
+    chunks(a).cmp(&chunks(b))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["story-10", "story-2", "story-1", "story-1a", "story"];
+        names.sort_by(|a, b| super::natural_order(a, b));
+        assert_eq!(
+            names,
+            ["story", "story-1", "story-1a", "story-2", "story-10"]
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! Adapters defined by site recipes rather than code.
+//!
+//! Most archives need nothing more than a handful of selectors: where the story links are on
+//! a listing, where the next page of the listing is, and where the story and its metadata are
+//! on a story page. A recipe declares exactly that, as TOML or YAML:
+//!
+//! ```toml
+//! name = "example"
+//! hosts = ["www.example.com"]
+//!
+//! [directory]
+//! links = "div.story-list-item > h3 > a"
+//! next_page = "ul.pagination a.next"
+//! meta.author = { selector = "div.page-title h2" }
+//!
+//! [document]
+//! content = "div.story-content"
+//! next_page = "a.next-part"
+//! meta.title = { selector = "title", pattern = "Example :: (.+)" }
+//! meta.tags = { selector = "ul.tags a", list = true }
+//! ```
+//!
+//! Metadata keys are those of `Meta::key`. Each is read from the elements matching
+//! `selector` (their text, or the attribute named by `attr`), optionally narrowed by the
+//! first capture group of `pattern`. Without a selector, the pattern is matched against the
+//! raw page.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Deserializer};
+use url::Url;
+
+use super::prelude::*;
+use crate::error::Error;
+
+/// Documents with more pages than this are cut short, in case "next" links never run out, and
+/// say so in their `truncated` metadata.
+const MAX_PAGES: usize = 500;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+    pub name: String,
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub directory: DirectoryRecipe,
+    #[serde(default)]
+    pub document: DocumentRecipe,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirectoryRecipe {
+    /// Selects links to documents. Without it, every url is treated as a single document.
+    pub links: Option<String>,
+    /// Only links whose href matches are followed.
+    #[serde(default, deserialize_with = "regex")]
+    pub link_pattern: Option<Regex>,
+    /// Selects the link to the next page of a listing.
+    pub next_page: Option<String>,
+    #[serde(default)]
+    pub meta: BTreeMap<String, Extract>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DocumentRecipe {
+    /// Selects the story content. Without it, the page is saved as-is.
+    pub content: Option<String>,
+    /// Selects the link to the next page of a multi-page story.
+    pub next_page: Option<String>,
+    #[serde(default)]
+    pub meta: BTreeMap<String, Extract>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Extract {
+    pub selector: Option<String>,
+    pub attr: Option<String>,
+    #[serde(default, deserialize_with = "regex")]
+    pub pattern: Option<Regex>,
+    /// Collect every match rather than the first.
+    #[serde(default)]
+    pub list: bool,
+}
+
+impl Recipe {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let recipe: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        recipe.check_selectors().map(|_| recipe)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, String> {
+        let recipe: Self = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+        recipe.check_selectors().map(|_| recipe)
+    }
+
+    /// nipper panics on a selector it can't parse, so every one is checked up front.
+    fn check_selectors(&self) -> Result<(), String> {
+        let directory = &self.directory;
+        let document = &self.document;
+        let mut selectors = vec![
+            ("directory.links".to_owned(), &directory.links),
+            ("directory.next_page".to_owned(), &directory.next_page),
+            ("document.content".to_owned(), &document.content),
+            ("document.next_page".to_owned(), &document.next_page),
+        ];
+        for (section, meta) in [("directory", &directory.meta), ("document", &document.meta)] {
+            for (key, extract) in meta {
+                selectors.push((format!("{}.meta.{}", section, key), &extract.selector));
+            }
+        }
+
+        for (field, selector) in selectors {
+            if let Some(selector) = selector {
+                if nipper::Matcher::new(selector).is_err() {
+                    return Err(format!("{}: invalid selector `{}`", field, selector));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a recipe, choosing the format by file extension.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let recipe = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Recipe::from_toml(&text),
+            Some("yaml" | "yml") => Recipe::from_yaml(&text),
+            _ => Err("expected a .toml, .yaml or .yml file".into()),
+        };
+        recipe.map_err(|e| Error::Recipe(path.into(), e))
+    }
+}
+
+/// Load every recipe in a directory, each on its own so that one broken recipe doesn't keep
+/// the others from loading.
+pub fn load_recipes(dir: &Path) -> Result<Vec<Result<Recipe>>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if let Some("toml" | "yaml" | "yml") = path.extension().and_then(|ext| ext.to_str()) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths.iter().map(|path| Recipe::load(path)).collect())
+}
+
+/// The directory from which user recipes are loaded, e.g. `~/.config/klit/recipes`.
+pub fn user_recipe_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("klit").join("recipes"))
+}
+
+#[derive(Clone)]
+pub struct BuildRecipeAdapter(Arc<Recipe>);
+
+impl BuildRecipeAdapter {
+    pub fn new(recipe: Recipe) -> Self {
+        Self(Arc::new(recipe))
+    }
+}
+
+impl BuildAdapter for BuildRecipeAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(RecipeAdapter::new(options.http, self.0.clone()))
+    }
+}
+
+pub struct RecipeAdapter {
+    http: Http,
+    recipe: Arc<Recipe>,
+}
+
+impl RecipeAdapter {
+    fn new(http: Http, recipe: Arc<Recipe>) -> Self {
+        Self { http, recipe }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for RecipeAdapter {
+    fn name(&self) -> &str {
+        &self.recipe.name
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        if self.recipe.directory.links.is_none() {
+            return Ok(DirectoryStream::new(Some(url.to_owned()), Metadata::new()));
+        }
+
+        let page = Url::parse(url)?;
+        let text = self.http.text(url).await?;
+        let recipe = &self.recipe.directory;
+        let meta = extract_meta(&recipe.meta, &nipper::Document::from(&text), &text);
+        let first = listing(recipe, &page, &text);
+
+        let stream = DirectoryStream::new(first.urls, meta);
+        Ok(match first.next {
+            Some(next) => {
+                let recipe = self.recipe.clone();
+                stream.with_paging(LinkPaging::new(
+                    self.http.clone(),
+                    page,
+                    Some(next),
+                    move |page, text| Ok(listing(&recipe.directory, page, text)),
+                ))
+            }
+            None => stream,
+        })
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let recipe = &self.recipe.document;
+        let mut page = Url::parse(&context.url)?;
+        let text = self.http.text(page.as_str()).await?;
+
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::Url(page.clone()));
+
+        let selector = match &recipe.content {
+            Some(selector) => selector,
+            None => {
+                let document = nipper::Document::from(&text);
+                meta.merge(extract_meta(&recipe.meta, &document, &text));
+                return Ok(Document {
+                    meta: context.merge(meta),
+                    text,
+                });
+            }
+        };
+
+        let (mut next, content) = {
+            let document = nipper::Document::from(&text);
+            meta.merge(extract_meta(&recipe.meta, &document, &text));
+            (
+                select_link(recipe.next_page.as_deref(), &document, &page),
+                document.select(selector).html().to_string(),
+            )
+        };
+
+        let mut parts = vec![content];
+        let mut visited: HashSet<_> = Some(page.clone()).into_iter().collect();
+        while let Some(url) = next.take() {
+            if !visited.insert(url.clone()) {
+                break;
+            }
+            if parts.len() >= MAX_PAGES {
+                meta.insert(
+                    Meta::Other("truncated".into()),
+                    format!("stopped after {} pages", MAX_PAGES),
+                );
+                break;
+            }
+            page = url;
+            let text = self.http.text(page.as_str()).await?;
+            let document = nipper::Document::from(&text);
+            next = select_link(recipe.next_page.as_deref(), &document, &page);
+            parts.push(document.select(selector).html().to_string());
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        for part in parts {
+            writeln!(buf, "{}", part.trim()).unwrap();
+        }
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+/// One page of a directory listing.
+fn listing(recipe: &DirectoryRecipe, page: &Url, text: &str) -> Listing {
+    let document = nipper::Document::from(text);
+    let urls = recipe
+        .links
+        .iter()
+        .flat_map(|selector| {
+            document
+                .select(selector)
+                .iter()
+                .filter_map(|link| link.attr("href"))
+                .collect::<Vec<_>>()
+        })
+        .filter(|href| {
+            recipe
+                .link_pattern
+                .as_ref()
+                .map(|pattern| pattern.is_match(href))
+                .unwrap_or(true)
+        })
+        .filter_map(|href| resolve::link(page, &href))
+        .map(String::from);
+
+    // Listings often link a story more than once (title, "read more"), so we dedupe.
+    let mut seen = HashSet::new();
+    let urls = urls.filter(|url| seen.insert(url.clone())).collect();
+
+    Listing {
+        urls,
+        next: select_link(recipe.next_page.as_deref(), &document, page),
+    }
+}
+
+fn select_link(selector: Option<&str>, document: &nipper::Document, page: &Url) -> Option<Url> {
+    let href = document.select(selector?).attr("href")?;
+    resolve::link(page, &href)
+}
+
+fn extract_meta(
+    extracts: &BTreeMap<String, Extract>,
+    document: &nipper::Document,
+    html: &str,
+) -> Metadata {
+    extracts
+        .iter()
+        .filter_map(|(key, extract)| {
+            let key = Meta::from_key(key);
+            let value = extract.value(&key, document, html)?;
+            Some((key, value))
+        })
+        .collect()
+}
+
+impl Extract {
+    fn value(&self, key: &Meta, document: &nipper::Document, html: &str) -> Option<Value> {
+        let values: Vec<String> = match &self.selector {
+            Some(selector) => document
+                .select(selector)
+                .iter()
+                .filter_map(|node| match &self.attr {
+                    Some(attr) => node.attr(attr).map(|value| value.to_string()),
+                    None => Some(node.text().to_string()),
+                })
+                .collect(),
+            None => vec![html.to_owned()],
+        };
+
+        let mut values = values.iter().filter_map(|value| match &self.pattern {
+            Some(pattern) => pattern
+                .captures(value)
+                .and_then(|cx| cx.get(1).or_else(|| cx.get(0)))
+                .map(|cx| cx.as_str().trim()),
+            None => Some(value.trim()),
+        });
+
+        if self.list {
+            let values = Value::list(values);
+            Some(values).filter(|values| values != &Value::List(Vec::new()))
+        } else {
+            values
+                .find(|value| !value.is_empty())
+                .map(|value| key.parse(value))
+        }
+    }
+}
+
+fn regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    let pattern: Option<String> = Option::deserialize(deserializer)?;
+    pattern
+        .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::Recipe;
+    use crate::document::Meta;
+
+    static RECIPE: &str = r#"
+        name = "example"
+        hosts = ["www.example.com"]
+
+        [directory]
+        links = "div.story > a"
+        link_pattern = "/story/"
+        next_page = "a.next"
+        meta.author = { selector = "h2.author" }
+
+        [document]
+        content = "div.content"
+        meta.title = { selector = "title", pattern = "Example :: (.+)" }
+        meta.tags = { selector = "a.tag", list = true }
+    "#;
+
+    static LISTING: &str = r#"
+        <h2 class="author">Somebody</h2>
+        <div class="story"><a href="/story/1">One</a></div>
+        <div class="story"><a href="../story/2?x=1">Two</a></div>
+        <div class="story"><a href="/story/1">One again</a></div>
+        <div class="story"><a href="/about">About</a></div>
+        <a class="next" href="?page=2">Next</a>
+    "#;
+
+    #[test]
+    fn parse_listing() {
+        let recipe = Recipe::from_toml(RECIPE).unwrap();
+        let page = Url::parse("https://www.example.com/authors/somebody/").unwrap();
+        let listing = super::listing(&recipe.directory, &page, LISTING);
+
+        assert_eq!(
+            listing.urls,
+            [
+                "https://www.example.com/story/1",
+                "https://www.example.com/authors/story/2?x=1"
+            ]
+        );
+        assert_eq!(
+            listing.next.unwrap().as_str(),
+            "https://www.example.com/authors/somebody/?page=2"
+        );
+
+        let document = nipper::Document::from(LISTING);
+        let meta = super::extract_meta(&recipe.directory.meta, &document, LISTING);
+        assert_eq!(meta.text(&Meta::Author), Some("Somebody"));
+    }
+
+    #[test]
+    fn extract_document_meta() {
+        let recipe = Recipe::from_toml(RECIPE).unwrap();
+        let html =
+            r#"<title>Example :: A Story</title><a class="tag">one</a><a class="tag">two</a>"#;
+        let document = nipper::Document::from(html);
+        let meta = super::extract_meta(&recipe.document.meta, &document, html);
+
+        assert_eq!(meta.text(&Meta::Title), Some("A Story"));
+        assert_eq!(meta.list(&Meta::Tags), ["one", "two"]);
+    }
+
+    #[test]
+    fn reject_bad_recipes() {
+        assert!(Recipe::from_toml("name = \"x\"\nhosts = []\nunknown = 1").is_err());
+        assert!(Recipe::from_toml(
+            "name = \"x\"\nhosts = []\n[document]\nmeta.title = { pattern = \"(\" }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reject_bad_selectors() {
+        let err = Recipe::from_toml("name = \"x\"\nhosts = []\n[directory]\nlinks = \"div >\"")
+            .unwrap_err();
+        assert_eq!(err, "directory.links: invalid selector `div >`");
+        let err = Recipe::from_yaml(
+            "name: x\nhosts: []\ndocument:\n  meta:\n    title: { selector: \"h1[\" }\n",
+        )
+        .unwrap_err();
+        assert_eq!(err, "document.meta.title: invalid selector `h1[`");
+    }
+
+    #[test]
+    fn parse_yaml() {
+        let recipe = Recipe::from_yaml(
+            "name: example\nhosts: [www.example.com]\ndocument:\n  content: div.content\n",
+        )
+        .unwrap();
+        assert_eq!(recipe.document.content.as_deref(), Some("div.content"));
+    }
+}
@@ -0,0 +1,144 @@
+//! Building the urls adapters follow from the links found on a page.
+//!
+//! Everything goes through `url::Url`, so relative paths, `../` segments, queries and
+//! percent-encoding are handled the way a browser would handle them.
+
+use url::Url;
+
+/// Resolve a link found on `page`.
+///
+/// Fragments are dropped, since they point into a page we would retrieve anyway. Links that
+/// cannot be retrieved, such as `mailto:` and `javascript:` links, resolve to nothing.
+pub fn link(page: &Url, href: &str) -> Option<Url> {
+    let mut url = page.join(href.trim()).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    url.set_fragment(None);
+    Some(url)
+}
+
+/// The url of a directory listing, as the base for the links it contains.
+///
+/// Servers list `/files/Somebody` and `/files/Somebody/` alike, but links on the listing are
+/// relative to the directory itself, so it needs its trailing slash.
+pub fn directory(url: &Url) -> Url {
+    let mut url = url.clone();
+    if !url.path().ends_with('/') {
+        let path = url.path().to_owned() + "/";
+        url.set_path(&path);
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+    url
+}
+
+/// Set one query parameter of a url, keeping any others.
+pub fn with_query(url: &Url, key: &str, value: &str) -> Url {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != key)
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair(key, value);
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    fn url(text: &str) -> Url {
+        Url::parse(text).unwrap()
+    }
+
+    #[test]
+    fn link() {
+        let page = url("https://www.asstr.org/files/Authors/Some%20Body/index.html?sort=name");
+        let resolve = |href| super::link(&page, href).map(String::from);
+
+        assert_eq!(
+            resolve("story.txt").as_deref(),
+            Some("https://www.asstr.org/files/Authors/Some%20Body/story.txt")
+        );
+        assert_eq!(
+            resolve("../Other/story.txt").as_deref(),
+            Some("https://www.asstr.org/files/Authors/Other/story.txt")
+        );
+        assert_eq!(
+            resolve("/files/Collections/").as_deref(),
+            Some("https://www.asstr.org/files/Collections/")
+        );
+        assert_eq!(
+            resolve("https://www.sexstories.com/story/1/a").as_deref(),
+            Some("https://www.sexstories.com/story/1/a")
+        );
+        assert_eq!(
+            resolve("//www.asstr.org/~kristen/").as_deref(),
+            Some("https://www.asstr.org/~kristen/")
+        );
+    }
+
+    #[test]
+    fn link_encoding() {
+        let page = url("https://www.asstr.org/files/Authors/Somebody/");
+        let resolve = |href| super::link(&page, href).map(String::from);
+
+        assert_eq!(
+            resolve("Old Joe's Story.txt").as_deref(),
+            Some("https://www.asstr.org/files/Authors/Somebody/Old%20Joe's%20Story.txt")
+        );
+        assert_eq!(
+            resolve("Old%20Joe's%20Story.txt").as_deref(),
+            Some("https://www.asstr.org/files/Authors/Somebody/Old%20Joe's%20Story.txt")
+        );
+        assert_eq!(
+            resolve(" story.txt#part2 ").as_deref(),
+            Some("https://www.asstr.org/files/Authors/Somebody/story.txt")
+        );
+        assert_eq!(resolve("mailto:somebody@example.com"), None);
+        assert_eq!(resolve("javascript:void(0)"), None);
+    }
+
+    #[test]
+    fn directory() {
+        assert_eq!(
+            super::directory(&url("https://www.asstr.org/files/Authors/Somebody")).as_str(),
+            "https://www.asstr.org/files/Authors/Somebody/"
+        );
+        assert_eq!(
+            super::directory(&url(
+                "https://www.asstr.org/files/Authors/Somebody/?C=M;O=A"
+            ))
+            .as_str(),
+            "https://www.asstr.org/files/Authors/Somebody/"
+        );
+    }
+
+    #[test]
+    fn with_query() {
+        assert_eq!(
+            super::with_query(
+                &url("https://www.thefetlibrary.com/stories/77"),
+                "part",
+                "2"
+            )
+            .as_str(),
+            "https://www.thefetlibrary.com/stories/77?part=2"
+        );
+        assert_eq!(
+            super::with_query(
+                &url("https://www.thefetlibrary.com/stories/77?part=1&view=full"),
+                "part",
+                "2"
+            )
+            .as_str(),
+            "https://www.thefetlibrary.com/stories/77?view=full&part=2"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Royal Road: fictions (`/fiction/<id>/<slug>`, or any chapter of one) and listings of them,
+//! e.g. an author's fictions (`/profile/<id>/fictions`).
+//!
+//! A fiction's table of contents gives each chapter with the date it was published; chapters
+//! are retrieved one by one and joined into one document, each in a section of its own
+//! (`<section id=chapter-<id>>`). The id of the last chapter is kept as `last_chapter`, to
+//! pick up from on a later run. Such a run gives the chapters it retrieved in its title, e.g.
+//! "Some Fiction (chapters 12-15)", and fails with `Error::NoNewChapters` if there are none.
+//!
+//! Settings:
+//!
+//! - `royalroad.since`: only retrieve chapters published after this date, e.g. `2023-05-01`,
+//!   or after the chapter with this id, e.g. `1234567`. A chapter id that isn't in the table
+//!   of contents fails with `Error::Setting`.
+
+use std::{collections::HashSet, fmt::Write};
+
+use chrono::NaiveDate;
+
+use super::prelude::*;
+use crate::{
+    document::{text::escape, value::parse_date},
+    error::Error,
+};
+
+pub struct BuildRoyalRoadAdapter;
+
+impl BuildAdapter for BuildRoyalRoadAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(RoyalRoadAdapter::new(options))
+    }
+}
+
+pub struct RoyalRoadAdapter {
+    http: Http,
+    since: Option<Since>,
+    hidden: Regex,
+}
+
+/// Where to pick up a fiction from.
+enum Since {
+    Date(NaiveDate),
+    Chapter(String),
+}
+
+impl RoyalRoadAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        let since = options.setting("royalroad.since").map(|since| {
+            let since = since.trim();
+            if since.chars().all(|u| u.is_ascii_digit()) {
+                Since::Chapter(since.into())
+            } else {
+                parse_date(since)
+                    .map(Since::Date)
+                    .unwrap_or_else(|| Since::Chapter(since.into()))
+            }
+        });
+        Self {
+            http: options.http,
+            since,
+            hidden: Regex::new(r"\.([\w-]+)\s*\{[^}]*display:\s*none").unwrap(),
+        }
+    }
+
+    /// The chapters to retrieve, out of all those in the table of contents.
+    fn wanted<'a>(&self, chapters: &'a [TocEntry], page: &Url) -> Result<&'a [TocEntry]> {
+        Ok(match &self.since {
+            None => chapters,
+            // A chapter that isn't there can't be picked up from, and retrieving the whole
+            // fiction instead would pass it off as what was asked for.
+            Some(Since::Chapter(id)) => match chapters.iter().position(|entry| entry.id == *id) {
+                Some(idx) => &chapters[idx + 1..],
+                None => {
+                    return Err(Error::Setting(
+                        "royalroad.since".into(),
+                        format!("no chapter {} in {}", id, page),
+                    ))
+                }
+            },
+            Some(Since::Date(date)) => {
+                // Chapters without a date are taken to be new.
+                let newer = |entry: &TocEntry| entry.published.is_none_or(|day| day > *date);
+                let idx = chapters.iter().position(newer).unwrap_or(chapters.len());
+                &chapters[idx..]
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for RoyalRoadAdapter {
+    fn name(&self) -> &'static str {
+        "royalroad"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let page = Url::parse(url)?;
+        if let Some(fiction) = fiction_url(&page) {
+            return Ok(DirectoryStream::new(Some(fiction.into()), Metadata::new()));
+        }
+
+        let text = self.http.text(url).await?;
+        let document = nipper::Document::from(&text);
+        let mut seen = HashSet::new();
+        let fictions: Vec<_> = document
+            .select("a[href*='/fiction/']")
+            .iter()
+            .filter_map(|link| resolve::link(&page, &link.attr("href")?))
+            .filter(|url| url.host_str() == page.host_str())
+            .filter_map(|url| fiction_url(&url))
+            .filter(|url| seen.insert(url.clone()))
+            .map(String::from)
+            .collect();
+        Ok(DirectoryStream::new(fictions, Metadata::new()))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let page = Url::parse(context.url())?;
+        let text = self.http.text(page.as_str()).await?;
+
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+        let toc = {
+            let document = nipper::Document::from(&text);
+            meta.merge(fiction_meta(&document, &page));
+            TocEntry::parse(&document, &page)
+        };
+        if let Some(published) = toc.first().and_then(|entry| entry.published) {
+            meta.insert(Meta::PublicationDate, Value::Date(published));
+        }
+        if let Some(updated) = toc.last().and_then(|entry| entry.published) {
+            meta.insert(Meta::LastUpdated, Value::Date(updated));
+        }
+
+        if toc.is_empty() {
+            return Err(Error::NoStory(page.into()));
+        }
+        let wanted = self.wanted(&toc, &page)?;
+        if wanted.is_empty() {
+            return Err(Error::NoNewChapters(page.into()));
+        }
+
+        // Picking up from a later chapter gives a document of its own, which is not to be
+        // mistaken for (or saved over) the whole fiction.
+        let skipped = toc.len() - wanted.len();
+        if skipped > 0 {
+            let chapters = match wanted.len() {
+                1 => format!("chapter {}", toc.len()),
+                _ => format!("chapters {}-{}", skipped + 1, toc.len()),
+            };
+            let title = meta.text(&Meta::Title).unwrap_or_default();
+            let title = format!("{} ({})", title, chapters);
+            meta.insert(Meta::Title, title);
+        }
+
+        let mut content = String::new();
+        for entry in wanted {
+            let text = self.http.text(entry.url.as_str()).await?;
+            let chapter = {
+                let document = nipper::Document::from(&text);
+
+                // Chapters carry paragraphs hidden by the page's stylesheet, which are not
+                // part of the story. Their classes are matched as attribute values, since
+                // they need not be valid identifiers, e.g. `1abc`.
+                let style = document.select("style").text();
+                for class in self.hidden.captures_iter(&style) {
+                    document
+                        .select(&format!("div.chapter-content [class~=\"{}\"]", &class[1]))
+                        .remove();
+                }
+                document
+                    .select("div.chapter-content")
+                    .html()
+                    .trim()
+                    .to_owned()
+            };
+
+            writeln!(
+                content,
+                "<section id=chapter-{}>\n<h2>{}</h2>",
+                entry.id,
+                escape(&entry.title)
+            )
+            .unwrap();
+            if let Some(published) = entry.published {
+                writeln!(
+                    content,
+                    "<p class=published><time datetime={date}>{date}</time></p>",
+                    date = published
+                )
+                .unwrap();
+            }
+            writeln!(content, "{}\n</section>", chapter).unwrap();
+        }
+        if let Some(last) = wanted.last() {
+            meta.insert(Meta::Other("last_chapter".into()), last.id.as_str());
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        buf.push_str(&content);
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+/// A chapter as listed in a fiction's table of contents.
+struct TocEntry {
+    id: String,
+    title: String,
+    url: Url,
+    published: Option<NaiveDate>,
+}
+
+impl TocEntry {
+    fn parse(document: &nipper::Document, page: &Url) -> Vec<Self> {
+        document
+            .select("table#chapters tr.chapter-row")
+            .iter()
+            .filter_map(|row| {
+                let link = row.select("td a").first();
+                let url = resolve::link(page, &link.attr("href")?)?;
+                let id = chapter_id(&url)?.to_owned();
+                Some(Self {
+                    id,
+                    title: link.text().trim().to_owned(),
+                    published: row
+                        .select("time")
+                        .attr("datetime")
+                        .and_then(|datetime| parse_date(&datetime)),
+                    url,
+                })
+            })
+            .collect()
+    }
+}
+
+fn fiction_meta(document: &nipper::Document, page: &Url) -> Metadata {
+    let mut meta = Metadata::new();
+    let title = document.select("div.fic-header h1").first().text();
+    if !title.trim().is_empty() {
+        meta.insert(Meta::Title, title.trim());
+    }
+    let author = document.select("div.fic-header h4 a").first().text();
+    if !author.trim().is_empty() {
+        meta.insert(Meta::Author, author.trim());
+    }
+    let summary = document.select("div.fiction-info div.description").text();
+    if !summary.trim().is_empty() {
+        meta.insert(Meta::Summary, summary.trim());
+    }
+
+    if let Some(cover) = document
+        .select("div.cover-art-container img")
+        .attr("src")
+        .and_then(|src| resolve::link(page, &src))
+    {
+        meta.insert(Meta::Other("cover".into()), Value::Url(cover));
+    }
+
+    let tags: Vec<_> = document
+        .select("span.tags a.fiction-tag")
+        .iter()
+        .map(|tag| tag.text().to_string())
+        .collect();
+    if !tags.is_empty() {
+        meta.insert(Meta::Tags, Value::list(tags));
+    }
+
+    // The header labels a fiction with its type and status, e.g. "ORIGINAL" and "ONGOING".
+    let status = document
+        .select("div.fiction-info span.label")
+        .iter()
+        .find_map(|label| match label.text().trim().to_lowercase().as_str() {
+            "ongoing" => Some("ongoing"),
+            "completed" => Some("complete"),
+            "hiatus" => Some("hiatus"),
+            "stub" => Some("stub"),
+            "dropped" => Some("dropped"),
+            _ => None,
+        });
+    if let Some(status) = status {
+        meta.insert(Meta::Status, status);
+    }
+    meta
+}
+
+/// The url of the fiction a url points into, e.g. from one of its chapters.
+fn fiction_url(url: &Url) -> Option<Url> {
+    let segments: Vec<_> = url.path_segments()?.filter(|x| !x.is_empty()).collect();
+    match segments[..] {
+        ["fiction", id, ..] if id.chars().all(|u| u.is_ascii_digit()) => {
+            let path = match segments.get(2) {
+                Some(slug) if *slug != "chapter" => format!("/fiction/{}/{}", id, slug),
+                _ => format!("/fiction/{}", id),
+            };
+            url.join(&path).ok()
+        }
+        _ => None,
+    }
+}
+
+/// The id of the chapter a url points to, e.g. `/fiction/<id>/<slug>/chapter/<id>/<slug>`.
+fn chapter_id(url: &Url) -> Option<&str> {
+    let mut segments = url.path_segments()?;
+    segments.find(|segment| *segment == "chapter")?;
+    segments.next().filter(|id| !id.is_empty())
+}
@@ -0,0 +1,539 @@
+//! Adapters written as Rhai scripts.
+//!
+//! For sites too quirky for a recipe. A script defines `hosts()`, `directory(url)` and
+//! `download(url)`, and may define `name()`:
+//!
+//! ```rhai
+//! fn hosts() { ["www.example.com"] }
+//!
+//! fn directory(url) {
+//!     let page = fetch(url);
+//!     select(page, "div.story > a").map(|a| resolve(url, a.attr("href")))
+//! }
+//!
+//! fn download(url) {
+//!     let page = fetch(url);
+//!     let title = select(page, "h1")[0].text;
+//!     document(select(page, "div.content")[0].html, #{ title: title, tags: ["a", "b"] })
+//! }
+//! ```
+//!
+//! `directory` returns a list of urls, or a map holding `urls` and directory-level `meta`.
+//! Scripts get `fetch(url)`, `select(html, selector)` (elements with `text`, `html` and
+//! `attr(name)`), `captures(text, pattern)`, `find_all(text, pattern)`, `resolve(base, href)`
+//! and `document(html, meta)`. They cannot import modules or touch the filesystem, and each
+//! call is limited in fetches, operations and wall-clock time.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rhai::{
+    module_resolvers::DummyModuleResolver, Array, Dynamic, Engine, EvalAltResult, Map, AST,
+};
+use tokio::runtime::Handle;
+use url::Url;
+
+use super::prelude::*;
+use crate::error::Error;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Limits applied to each call into a script.
+#[derive(Clone, Debug)]
+pub struct ScriptLimits {
+    pub max_fetches: usize,
+    pub max_duration: Duration,
+    pub max_operations: u64,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_fetches: 500,
+            max_duration: Duration::from_secs(300),
+            max_operations: 50_000_000,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Script {
+    name: String,
+    hosts: Vec<String>,
+    path: PathBuf,
+    ast: Arc<AST>,
+    limits: ScriptLimits,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)?;
+        Self::compile(path, &source)
+    }
+
+    /// Compile a script; `path` is used to name the script and in error messages.
+    pub fn compile(path: &Path, source: &str) -> Result<Self> {
+        let error = |e: String| Error::Script(path.into(), e);
+        let engine = sandbox(&ScriptLimits::default(), Instant::now());
+        let ast = engine.compile(source).map_err(|e| error(e.to_string()))?;
+
+        let hosts: Array = engine
+            .call_fn(&mut Default::default(), &ast, "hosts", ())
+            .map_err(|e| error(e.to_string()))?;
+        let hosts = hosts
+            .into_iter()
+            .map(|host| {
+                host.into_string()
+                    .map_err(|_| error("hosts must be strings".into()))
+            })
+            .collect::<Result<_>>()?;
+
+        let name = if ast.iter_functions().any(|f| f.name == "name") {
+            engine
+                .call_fn(&mut Default::default(), &ast, "name", ())
+                .map_err(|e| error(e.to_string()))?
+        } else {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("script")
+                .to_owned()
+        };
+
+        Ok(Self {
+            name,
+            hosts,
+            path: path.into(),
+            ast: Arc::new(ast),
+            limits: ScriptLimits::default(),
+        })
+    }
+
+    pub fn with_limits(mut self, limits: ScriptLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    fn call(&self, function: &str, url: String, http: Http, handle: Handle) -> Result<Dynamic> {
+        let started = Instant::now();
+        let mut engine = sandbox(&self.limits, started);
+        register_api(&mut engine, &self.limits, started, http, handle);
+        engine
+            .call_fn(&mut Default::default(), &self.ast, function, (url,))
+            .map_err(|e| self.error(e.to_string()))
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::Script(self.path.clone(), message.into())
+    }
+}
+
+/// Load every script in a directory, each on its own so that one broken script doesn't keep
+/// the others from loading.
+pub fn load_scripts(dir: &Path) -> Result<Vec<Result<Script>>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("rhai") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths.iter().map(|path| Script::load(path)).collect())
+}
+
+/// The directory from which user scripts are loaded, e.g. `~/.config/klit/scripts`.
+pub fn user_script_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("klit").join("scripts"))
+}
+
+#[derive(Clone)]
+pub struct BuildScriptAdapter(Script);
+
+impl BuildScriptAdapter {
+    pub fn new(script: Script) -> Self {
+        Self(script)
+    }
+}
+
+impl BuildAdapter for BuildScriptAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(ScriptAdapter::new(options.http, self.0.clone()))
+    }
+}
+
+pub struct ScriptAdapter {
+    http: Http,
+    script: Script,
+}
+
+impl ScriptAdapter {
+    fn new(http: Http, script: Script) -> Self {
+        Self { http, script }
+    }
+
+    /// Scripts are synchronous, so they run on the blocking pool and fetch by blocking on the
+    /// runtime they were called from.
+    async fn call(&self, function: &'static str, url: &str) -> Result<Dynamic> {
+        let script = self.script.clone();
+        let http = self.http.clone();
+        let url = url.to_owned();
+        let handle = Handle::current();
+        tokio::task::spawn_blocking(move || script.call(function, url, http, handle))
+            .await
+            .map_err(|e| self.script.error(e.to_string()))?
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for ScriptAdapter {
+    fn name(&self) -> &str {
+        &self.script.name
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let result = self.call("directory", url).await?;
+        let (urls, meta) = if result.is_map() {
+            let mut map = result.cast::<Map>();
+            let urls = map.remove("urls").unwrap_or_default();
+            let meta = map.remove("meta").and_then(|meta| meta.try_cast::<Map>());
+            (urls, meta.map(metadata).unwrap_or_default())
+        } else {
+            (result, Metadata::new())
+        };
+
+        let urls = urls
+            .try_cast::<Array>()
+            .ok_or_else(|| self.script.error("directory must return a list of urls"))?
+            .into_iter()
+            .map(|url| {
+                url.into_string()
+                    .map_err(|_| self.script.error("urls must be strings"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DirectoryStream::new(urls, meta))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let result = self.call("download", context.url()).await?;
+        let ScriptDocument { mut meta, text } = result
+            .try_cast::<ScriptDocument>()
+            .ok_or_else(|| self.script.error("download must return a document"))?;
+        meta.insert_default(Meta::SourceUrl, Value::url(context.url()));
+        Ok(Document {
+            meta: context.merge(meta),
+            text,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct ScriptDocument {
+    meta: Metadata,
+    text: String,
+}
+
+#[derive(Clone)]
+struct Element {
+    text: String,
+    html: String,
+    attrs: HashMap<String, String>,
+}
+
+/// An engine with resource limits and no access to modules, whose time runs from `started`.
+fn sandbox(limits: &ScriptLimits, started: Instant) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_operations(limits.max_operations)
+        .set_max_call_levels(64)
+        .set_max_expr_depths(128, 64)
+        .set_max_string_size(64 << 20)
+        .set_max_array_size(1 << 20)
+        .set_max_map_size(1 << 16)
+        // Stdout is reserved for klit's own output.
+        .on_print(|text| eprintln!("{}", text))
+        .on_debug(|text, _, _| eprintln!("{}", text));
+
+    let max_duration = limits.max_duration;
+    engine.on_progress(move |_| {
+        if started.elapsed() > max_duration {
+            Some("time limit exceeded".into())
+        } else {
+            None
+        }
+    });
+    engine
+}
+
+fn register_api(
+    engine: &mut Engine,
+    limits: &ScriptLimits,
+    started: Instant,
+    http: Http,
+    handle: Handle,
+) {
+    let fetches = AtomicUsize::new(0);
+    let max_fetches = limits.max_fetches;
+    let max_duration = limits.max_duration;
+    engine.register_fn("fetch", move |url: &str| -> ScriptResult<String> {
+        if fetches.fetch_add(1, Ordering::Relaxed) >= max_fetches {
+            return Err("fetch limit exceeded".into());
+        }
+        // Operations aren't counted while we wait on the network, so a fetch gets only what
+        // is left of the time limit.
+        let left = max_duration
+            .checked_sub(started.elapsed())
+            .ok_or("time limit exceeded")?;
+        handle
+            .block_on(async { tokio::time::timeout(left, http.text(url)).await })
+            .map_err(|_| "time limit exceeded")?
+            .map_err(|e| e.to_string().into())
+    });
+
+    engine
+        .register_type_with_name::<Element>("Element")
+        .register_get("text", |element: &mut Element| element.text.clone())
+        .register_get("html", |element: &mut Element| element.html.clone())
+        .register_fn("attr", |element: &mut Element, name: &str| {
+            element
+                .attrs
+                .get(name)
+                .cloned()
+                .map(Dynamic::from)
+                .unwrap_or(Dynamic::UNIT)
+        })
+        .register_fn(
+            "select",
+            |html: &str, selector: &str| -> ScriptResult<Array> { select(html, selector) },
+        )
+        .register_fn(
+            "select",
+            |element: &mut Element, selector: &str| -> ScriptResult<Array> {
+                select(&element.html, selector)
+            },
+        );
+
+    engine
+        .register_fn(
+            "captures",
+            |text: &str, pattern: &str| -> ScriptResult<Array> {
+                let pattern = Regex::new(pattern).map_err(|e| e.to_string())?;
+                Ok(pattern
+                    .captures(text)
+                    .map(|cx| {
+                        cx.iter()
+                            .map(|group| {
+                                group
+                                    .map(|group| Dynamic::from(group.as_str().to_owned()))
+                                    .unwrap_or(Dynamic::UNIT)
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default())
+            },
+        )
+        .register_fn(
+            "find_all",
+            |text: &str, pattern: &str| -> ScriptResult<Array> {
+                let pattern = Regex::new(pattern).map_err(|e| e.to_string())?;
+                Ok(pattern
+                    .captures_iter(text)
+                    .filter_map(|cx| cx.get(1).or_else(|| cx.get(0)))
+                    .map(|group| Dynamic::from(group.as_str().to_owned()))
+                    .collect())
+            },
+        )
+        .register_fn(
+            "resolve",
+            |base: &str, href: &str| -> ScriptResult<String> {
+                Url::parse(base)
+                    .and_then(|base| base.join(href))
+                    .map(String::from)
+                    .map_err(|e| e.to_string().into())
+            },
+        );
+
+    engine
+        .register_type_with_name::<ScriptDocument>("Document")
+        .register_fn("document", |text: &str| ScriptDocument {
+            meta: Metadata::new(),
+            text: text.into(),
+        })
+        .register_fn("document", |text: &str, meta: Map| ScriptDocument {
+            meta: metadata(meta),
+            text: text.into(),
+        });
+}
+
+fn select(html: &str, selector: &str) -> ScriptResult<Array> {
+    // nipper's `select` panics on a selector it can't parse.
+    let matcher =
+        nipper::Matcher::new(selector).map_err(|_| format!("invalid selector `{}`", selector))?;
+    let document = nipper::Document::from(html);
+    let selection = document.select_matcher(&matcher);
+    Ok(selection
+        .nodes()
+        .iter()
+        .map(|node| {
+            Dynamic::from(Element {
+                text: node.text().to_string(),
+                html: node.html().to_string(),
+                attrs: node
+                    .attrs()
+                    .into_iter()
+                    .map(|attr| (attr.name.local.to_string(), attr.value.to_string()))
+                    .collect(),
+            })
+        })
+        .collect())
+}
+
+/// Convert a script's metadata map, keyed by the names given by `Meta::key`.
+fn metadata(map: Map) -> Metadata {
+    map.into_iter()
+        .filter_map(|(key, value)| {
+            let key = Meta::from_key(&key);
+            let value = if value.is_array() {
+                let values: Vec<_> = value
+                    .cast::<Array>()
+                    .into_iter()
+                    .map(|value| value.to_string())
+                    .collect();
+                Value::list(values)
+            } else if value.is_int() {
+                Value::Integer(value.as_int().ok()?)
+            } else if value.is_unit() {
+                return None;
+            } else {
+                key.parse(&value.to_string())
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::TcpListener, path::Path, time::Duration};
+
+    use url::Url;
+
+    use super::{Script, ScriptDocument, ScriptLimits};
+    use crate::{adapter::http::Http, document::Meta};
+
+    static SCRIPT: &str = r#"
+        fn hosts() { ["www.example.com"] }
+        fn name() { "example" }
+
+        fn directory(url) {
+            let page = `<div class="story"><a href="/s/1">One</a></div>`;
+            #{
+                urls: select(page, "div.story > a").map(|a| resolve(url, a.attr("href"))),
+                meta: #{ author: "Somebody" },
+            }
+        }
+
+        fn download(url) {
+            let page = `<title>Story :: One</title><div class="content">Text</div>`;
+            let title = captures(page, "Story :: ([^<]+)")[1];
+            document(select(page, "div.content")[0].html, #{ title: title, tags: ["a", "b"], word_count: 1 })
+        }
+
+        fn spin(url) { loop {} }
+        fn fetch_twice(url) { fetch(url); fetch(url) }
+        fn bad_selector(url) { select("<p>x</p>", "p >") }
+    "#;
+
+    fn call(script: &Script, function: &str) -> crate::Result<rhai::Dynamic> {
+        call_with(script, function, Http::new())
+    }
+
+    /// Calls run on the blocking pool as they do in an adapter, so the runtime is driven
+    /// while they fetch.
+    fn call_with(script: &Script, function: &str, http: Http) -> crate::Result<rhai::Dynamic> {
+        let runtime = crate::adapter::runtime();
+        let handle = runtime.handle().clone();
+        let (script, function) = (script.clone(), function.to_owned());
+        runtime
+            .block_on(async move {
+                tokio::task::spawn_blocking(move || {
+                    script.call(&function, "https://www.example.com/a/".into(), http, handle)
+                })
+                .await
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn compile_script() {
+        let script = Script::compile(Path::new("example.rhai"), SCRIPT).unwrap();
+        assert_eq!(script.hosts(), ["www.example.com"]);
+        assert_eq!(script.name, "example");
+    }
+
+    #[test]
+    fn run_script() {
+        let script = Script::compile(Path::new("example.rhai"), SCRIPT).unwrap();
+
+        let directory = call(&script, "directory").unwrap().cast::<rhai::Map>();
+        let urls = directory["urls"].clone().into_array().unwrap();
+        assert_eq!(urls[0].to_string(), "https://www.example.com/s/1");
+
+        let document = call(&script, "download").unwrap().cast::<ScriptDocument>();
+        assert_eq!(document.text, r#"<div class="content">Text</div>"#);
+        assert_eq!(document.meta.text(&Meta::Title), Some("One"));
+        assert_eq!(document.meta.list(&Meta::Tags), ["a", "b"]);
+        assert_eq!(document.meta.integer(&Meta::WordCount), Some(1));
+
+        let error = call(&script, "bad_selector").unwrap_err().to_string();
+        assert!(error.contains("invalid selector `p >`"), "{}", error);
+    }
+
+    #[test]
+    fn enforce_limits() {
+        let limits = ScriptLimits {
+            max_fetches: 0,
+            max_operations: 10_000,
+            ..Default::default()
+        };
+        let script = Script::compile(Path::new("example.rhai"), SCRIPT)
+            .unwrap()
+            .with_limits(limits);
+        assert!(call(&script, "spin").is_err());
+
+        let error = call(&script, "fetch_twice").unwrap_err().to_string();
+        assert!(error.contains("fetch limit exceeded"), "{}", error);
+
+        // Time spent waiting on a fetch counts against the time limit; this server never
+        // answers.
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let origin = format!("http://{}", server.local_addr().unwrap());
+        let http = Http::new().with_overrides(HashMap::from([(
+            "www.example.com".to_owned(),
+            Url::parse(&origin).unwrap(),
+        )]));
+        let limits = ScriptLimits {
+            max_duration: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let script = Script::compile(Path::new("example.rhai"), SCRIPT)
+            .unwrap()
+            .with_limits(limits);
+        let error = call_with(&script, "fetch_twice", http)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("time limit exceeded"), "{}", error);
+    }
+}
@@ -3,31 +3,36 @@ use super::prelude::*;
 pub struct BuildSexStoriesAdapter;
 
 impl BuildAdapter for BuildSexStoriesAdapter {
-    fn build(&self) -> Box<dyn Adapter + 'static> {
-        Box::new(SexStoriesAdapter::new())
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(SexStoriesAdapter::new(options))
     }
 }
 
 pub struct SexStoriesAdapter {
-    client: Client,
+    http: Http,
     title: Regex,
 }
 
 impl SexStoriesAdapter {
-    fn new() -> Self {
+    fn new(options: AdapterOptions) -> Self {
         Self {
-            client: Client::builder().user_agent(USER_AGENT).build().unwrap(),
+            http: options.http,
             title: Regex::new("(.+)\\n").unwrap(),
         }
     }
 }
 
-impl Adapter for SexStoriesAdapter {
-    fn directory(&self, url: &str) -> Result<DirectoryUrls> {
-        let text = self.client.get(url).send()?.text()?;
+#[async_trait]
+impl AsyncAdapter for SexStoriesAdapter {
+    fn name(&self) -> &'static str {
+        "sexstories"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let text = self.http.text(url).await?;
         let document = nipper::Document::from(&text);
 
-        let mut meta = HashMap::new();
+        let mut meta = Metadata::new();
         if let Some(author) = document
             .select("h3.notice > div.left")
             .iter()
@@ -43,23 +48,21 @@ impl Adapter for SexStoriesAdapter {
         // way to tell the difference between the two--except of course that one comes first.
         // Looks like we're just going to take the "first."
 
+        let page = Url::parse(url)?;
         let stories = document.select("h3.notice + table").iter().next();
         let stories = stories
             .into_iter()
             .flat_map(|x| x.select("td a").iter().filter_map(|x| x.attr("href")))
-            .map(RelativeUrl);
+            .filter_map(|href| resolve::link(&page, &href));
 
-        Ok(DirectoryUrls {
-            urls: stories.map(|story| story.url()).collect(),
-            page: None,
-            meta,
-        })
+        Ok(DirectoryStream::new(stories.map(String::from), meta))
     }
 
-    fn download(&self, context: DocumentUrl) -> Result<Document> {
-        let text = self.client.get(&context.url).send()?.text()?;
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let text = self.http.text(&context.url).await?;
         let document = nipper::Document::from(&text);
-        let mut meta = context.meta;
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(&context.url));
         if let Some(title) = self
             .title
             .captures(&document.select("div.story_info > h2").text())
@@ -68,14 +71,9 @@ impl Adapter for SexStoriesAdapter {
         {
             meta.insert(Meta::Title, title.to_string());
         }
-        Ok(Document { meta, text })
-    }
-}
-
-struct RelativeUrl<T>(T);
-
-impl<T: AsRef<str>> RelativeUrl<T> {
-    fn url(&self) -> String {
-        format!("https://sexstories.com{}", self.0.as_ref())
+        Ok(Document {
+            meta: context.merge(meta),
+            text,
+        })
     }
 }
@@ -0,0 +1,283 @@
+//! StoriesOnline: stories (`/s/<id>/<slug>`, or any chapter of one), author pages
+//! (`/a/<name>`) and series (`/series/<id>/<slug>`).
+//!
+//! Most stories are only shown in full to members; anonymous visitors get the first part. With
+//! credentials the adapter logs in before its first download, and the session is kept for
+//! every later request. Without them, a story cut short is reported as
+//! `Error::LoginRequired` rather than saved incomplete.
+//!
+//! Settings:
+//!
+//! - `storiesonline.username`, `storiesonline.password`: the account to log in with. The
+//!   password can also be given in the `KLIT_SOL_PASSWORD` environment variable, to keep it off
+//!   the command line.
+
+use std::{
+    collections::HashSet,
+    env,
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::prelude::*;
+use crate::{document::text::escape, error::Error};
+
+/// Stories with more chapters than this are cut short, and say so in their `truncated`
+/// metadata.
+const MAX_CHAPTERS: usize = 500;
+
+pub struct BuildStoriesOnlineAdapter;
+
+impl BuildAdapter for BuildStoriesOnlineAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(StoriesOnlineAdapter::new(options))
+    }
+}
+
+pub struct StoriesOnlineAdapter {
+    http: Http,
+    base: Url,
+    credentials: Option<(String, String)>,
+    logged_in: AtomicBool,
+    series_position: Regex,
+}
+
+impl StoriesOnlineAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        let username = options
+            .setting("storiesonline.username")
+            .map(ToOwned::to_owned);
+        let password = options
+            .setting("storiesonline.password")
+            .map(ToOwned::to_owned)
+            .or_else(|| env::var("KLIT_SOL_PASSWORD").ok());
+        Self {
+            base: options.base_or("https://storiesonline.net/"),
+            http: options.http,
+            credentials: username.zip(password),
+            logged_in: AtomicBool::new(false),
+            series_position: Regex::new(r"#\s*(\d+)").unwrap(),
+        }
+    }
+
+    /// Log in, unless we already have or have no account to log in with.
+    async fn login(&self) -> Result<()> {
+        let (username, password) = match &self.credentials {
+            Some(credentials) if !self.logged_in.load(Ordering::Relaxed) => credentials,
+            _ => return Ok(()),
+        };
+
+        let url = self.base.join("sol-secure/login.php")?;
+        let response = self
+            .http
+            .post_form(
+                url.as_str(),
+                &[
+                    ("email", username),
+                    ("password", password),
+                    ("cmd", "LOGIN"),
+                ],
+            )
+            .await?;
+
+        // Members get a link to log out on every page.
+        let logged_in = nipper::Document::from(&response.body)
+            .select("a[href*='logout']")
+            .exists();
+        if !logged_in {
+            return Err(Error::Login(
+                self.base.host_str().unwrap_or_default().into(),
+            ));
+        }
+        self.logged_in.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for StoriesOnlineAdapter {
+    fn name(&self) -> &'static str {
+        "storiesonline"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let page = Url::parse(url)?;
+        if let Some(story) = story_url(&page) {
+            return Ok(DirectoryStream::new(Some(story.into()), Metadata::new()));
+        }
+
+        let text = self.http.text(url).await?;
+        let document = nipper::Document::from(&text);
+        let mut seen = HashSet::new();
+        let stories: Vec<_> = document
+            .select("a[href*='/s/']")
+            .iter()
+            .filter_map(|link| resolve::link(&page, &link.attr("href")?))
+            .filter_map(|url| story_url(&url))
+            .filter(|url| seen.insert(url.clone()))
+            .map(String::from)
+            .collect();
+
+        let mut meta = Metadata::new();
+        let heading = document.select("h1").first().text().trim().to_owned();
+        if page.path().starts_with("/series/") {
+            if !heading.is_empty() {
+                meta.insert(Meta::Series, heading);
+            }
+
+            let mut directory = DirectoryStream::new(stories.clone(), meta);
+            for (idx, story) in stories.into_iter().enumerate() {
+                let mut meta = Metadata::new();
+                meta.insert(Meta::SeriesIndex, Value::Integer(idx as i64 + 1));
+                directory = directory.with_document_meta(story, meta);
+            }
+            return Ok(directory);
+        }
+
+        if !heading.is_empty() {
+            meta.insert(Meta::Author, heading);
+        }
+        Ok(DirectoryStream::new(stories, meta))
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        self.login().await?;
+
+        let page = Url::parse(context.url())?;
+        let text = self.http.text(page.as_str()).await?;
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+
+        let (mut next, headings, mut content) = {
+            let document = nipper::Document::from(&text);
+            meta.merge(self.story_meta(&document));
+
+            // Stories in chapters list them on their first page; others are on it in full.
+            let headings: Vec<(Url, String)> = document
+                .select("div#index-list a")
+                .iter()
+                .filter_map(|link| {
+                    let url = resolve::link(&page, &link.attr("href")?)?;
+                    Some((url, link.text().trim().to_owned()))
+                })
+                .collect();
+            match headings.first() {
+                Some((first, _)) => (Some(first.clone()), headings, Vec::new()),
+                None => {
+                    let chapter = Chapter::parse(&document, &page)?;
+                    (chapter.next, headings, vec![(None, chapter.content)])
+                }
+            }
+        };
+
+        // Follow each chapter's link to the next.
+        let mut visited: HashSet<_> = Some(page).into_iter().collect();
+        while let Some(url) = next.take() {
+            if !visited.insert(url.clone()) {
+                break;
+            }
+            if content.len() >= MAX_CHAPTERS {
+                meta.insert(
+                    Meta::Other("truncated".into()),
+                    format!("stopped after {} chapters", MAX_CHAPTERS),
+                );
+                break;
+            }
+            let text = self.http.text(url.as_str()).await?;
+            let chapter = Chapter::parse(&nipper::Document::from(&text), &url)?;
+            let heading = headings
+                .iter()
+                .find(|(chapter, _)| *chapter == url)
+                .map(|(_, heading)| heading.clone());
+            next = chapter.next;
+            content.push((heading, chapter.content));
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        for (heading, content) in content {
+            if let Some(heading) = heading {
+                writeln!(buf, "<h2>{}</h2>", escape(&heading)).unwrap();
+            }
+            writeln!(buf, "{}", content).unwrap();
+        }
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+impl StoriesOnlineAdapter {
+    fn story_meta(&self, document: &nipper::Document) -> Metadata {
+        let mut meta = Metadata::new();
+        let title = document.select("div#story-header h1").text();
+        if !title.trim().is_empty() {
+            meta.insert(Meta::Title, title.trim());
+        }
+        let author = document.select("div#story-header a[rel='author']").text();
+        if !author.trim().is_empty() {
+            meta.insert(Meta::Author, author.trim());
+        }
+
+        // The details are a list of labelled values.
+        let labels = document.select("dl.story-details > dt");
+        let values = document.select("dl.story-details > dd");
+        for (label, value) in labels.iter().zip(values.iter()) {
+            let text = value.text().trim().to_owned();
+            let key = match label.text().trim().trim_end_matches(':') {
+                "Codes" => Meta::Tags,
+                "Score" => Meta::Other("score".into()),
+                "Size" => Meta::Other("size".into()),
+                "Posted" => Meta::PublicationDate,
+                "Updated" => Meta::LastUpdated,
+                "Series" => {
+                    meta.insert(Meta::Series, value.select("a").text().trim());
+                    if let Some(position) = self.series_position.captures(&text) {
+                        meta.insert(Meta::SeriesIndex, Value::integer(&position[1]));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            meta.insert(key.clone(), key.parse(&text));
+        }
+        meta
+    }
+}
+
+/// One page of a story's text.
+struct Chapter {
+    content: String,
+    next: Option<Url>,
+}
+
+impl Chapter {
+    fn parse(document: &nipper::Document, page: &Url) -> Result<Self> {
+        // Anonymous visitors are shown a notice where the rest of the story would be.
+        if document.select("div#login-notice").exists() {
+            return Err(Error::LoginRequired(page.to_string()));
+        }
+        Ok(Self {
+            content: document.select("article#story").html().trim().to_owned(),
+            next: document
+                .select("a[rel='next']")
+                .attr("href")
+                .and_then(|href| resolve::link(page, &href)),
+        })
+    }
+}
+
+/// The url of the story a url points into, e.g. from one of its chapters (`/s/<id>:<n>/...`).
+fn story_url(url: &Url) -> Option<Url> {
+    let segments: Vec<_> = url.path_segments()?.collect();
+    let (id, slug) = match segments[..] {
+        ["s", id] => (id, None),
+        ["s", id, slug, ..] => (id, Some(slug)),
+        _ => return None,
+    };
+    let id = id.split(':').next()?;
+    let path = match slug {
+        Some(slug) if !slug.is_empty() => format!("/s/{}/{}", id, slug),
+        _ => format!("/s/{}", id),
+    };
+    url.join(&path).ok()
+}
@@ -5,38 +5,44 @@ use super::prelude::*;
 pub struct BuildFetLibraryAdapter;
 
 impl BuildAdapter for BuildFetLibraryAdapter {
-    fn build(&self) -> Box<dyn Adapter + 'static> {
-        Box::new(FetLibraryAdapter::new())
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(FetLibraryAdapter::new(options))
     }
 }
 
 pub struct FetLibraryAdapter {
-    client: Client,
+    http: Http,
     title: Regex,
     part: Regex,
 }
 
 impl FetLibraryAdapter {
-    fn new() -> Self {
+    fn new(options: AdapterOptions) -> Self {
         Self {
-            client: Client::builder().user_agent(USER_AGENT).build().unwrap(),
+            http: options.http,
             title: Regex::new("The Fet Library :: (.+)").unwrap(),
-            part: Regex::new(r"\?part=\d+").unwrap(),
+            part: Regex::new(r"\?part=(\d+)").unwrap(),
         }
     }
 }
 
-impl Adapter for FetLibraryAdapter {
-    fn directory(&self, url: &str) -> Result<DirectoryUrls> {
-        let text = self.client.get(url).send()?.text()?;
+#[async_trait]
+impl AsyncAdapter for FetLibraryAdapter {
+    fn name(&self) -> &'static str {
+        "thefetlibrary"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let text = self.http.text(url).await?;
         let document = nipper::Document::from(&text);
+        let page = Url::parse(url)?;
         let items = document
             .select("div.story-list-item > h3 > a")
             .iter()
             .filter_map(|item| item.attr("href"))
-            .map(RelativeUrl);
+            .filter_map(|href| resolve::link(&page, &href));
 
-        let mut meta = HashMap::new();
+        let mut meta = Metadata::new();
         let author = document
             .select("div.jumbotron.page-title div.container.text-center div h2")
             .text();
@@ -45,34 +51,41 @@ impl Adapter for FetLibraryAdapter {
             meta.insert(Meta::Author, author.to_string());
         }
 
-        Ok(DirectoryUrls {
-            urls: items.map(|url| url.url()).collect(),
-            page: None,
-            meta,
-        })
+        Ok(DirectoryStream::new(items.map(String::from), meta))
     }
 
-    fn download(&self, context: DocumentUrl) -> Result<Document> {
-        let mut meta = context.meta;
-        let text = self.client.get(&context.url).send()?.text()?;
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let mut meta = Metadata::new();
+        meta.insert(Meta::SourceUrl, Value::url(&context.url));
+        let text = self.http.text(&context.url).await?;
 
-        let document = nipper::Document::from(&text);
-        if let Some(title) = self
-            .title
-            .captures(&document.select("title").text())
-            .and_then(|x| x.get(1).map(|x| x.as_str()))
-        {
-            meta.insert(Meta::Title, title.into());
+        // nipper documents can't be held across an await, so we take everything we need from
+        // the first page up front.
+        let (tags, content) = {
+            let document = nipper::Document::from(&text);
+            if let Some(title) = self
+                .title
+                .captures(&document.select("title").text())
+                .and_then(|x| x.get(1).map(|x| x.as_str()))
+            {
+                meta.insert(Meta::Title, title);
+            }
+
+            // We use these later. Hopefully tags are per story, not per section.
+            let tags = document
+                .select("div.jumbotron.page-subtitle > div.container.text-center")
+                .text()
+                .trim()
+                .to_owned();
+            (tags, select_content(&document))
+        };
+
+        if !tags.is_empty() {
+            meta.insert(Meta::Tags, Value::list(tags.split(',')));
         }
 
-        // We use these later. Hopefully tags are per story, not per section.
-        let tags = document
-            .select("div.jumbotron.page-subtitle > div.container.text-center")
-            .text();
-        let tags = tags.trim();
-
         // The plan is to take ONLY the story content and generate a new document on that basis.
-        let mut parts = vec![select_content(&document)];
+        let mut parts = vec![content];
 
         // When we get the initial text of the story, we also receive links to all other portions
         // of said story. Unfortunately, one of these links (the "next" link) is repeated. Having
@@ -80,25 +93,26 @@ impl Adapter for FetLibraryAdapter {
         let mut remaining_parts: Vec<_> = self
             .part
             .captures_iter(&text)
-            .filter_map(|x| x.get(0).map(|x| x.as_str()))
+            .filter_map(|x| x.get(1).map(|x| x.as_str()))
             .collect();
         remaining_parts.pop();
 
+        let page = Url::parse(&context.url)?;
         for part in remaining_parts {
-            let url = context.url.to_string() + part;
-            let text = self.client.get(&url).send()?.text()?;
-            let document = nipper::Document::from(&text);
-            parts.push(select_content(&document));
+            let url = resolve::with_query(&page, "part", part);
+            let text = self.http.text(url.as_str()).await?;
+            parts.push(select_content(&nipper::Document::from(&text)));
         }
 
         // Now that we have all the parts, we actually want to generate new html content. Which
         // always fucking sucks, but did you have a better idea? No? I didn't think so. Fuck off.
+        let meta = context.merge(meta);
         let mut buf = String::new();
         writeln!(
             buf,
             "<title>{title} - {author}</title>\n<h1>{title}</h1>\n<p>By <span id=author>{author}</span></p>",
-            title = meta.get(&Meta::Title).unwrap(),
-            author = meta.get(&Meta::Author).unwrap()
+            title = meta.text(&Meta::Title).unwrap_or_default(),
+            author = meta.text(&Meta::Author).unwrap_or_default()
         )
         .unwrap();
         writeln!(buf, "<p id=tags>tags: {}</p>", tags).unwrap();
@@ -118,11 +132,3 @@ fn select_content(document: &nipper::Document) -> String {
         .trim()
         .to_string()
 }
-
-struct RelativeUrl<T>(T);
-
-impl<T: AsRef<str>> RelativeUrl<T> {
-    fn url(&self) -> String {
-        format!("https://www.thefetlibrary.com{}", self.0.as_ref())
-    }
-}
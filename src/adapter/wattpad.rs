@@ -0,0 +1,278 @@
+//! Wattpad: stories (`/story/<id>-<slug>`, or any part of one, `/<id>-<slug>`) and a user's
+//! works (`/user/<name>`), read through the site's JSON API. Any other page is searched for
+//! links to stories, e.g. a reading list.
+
+use std::{collections::HashSet, fmt::Write};
+
+use super::prelude::*;
+use crate::{document::text::escape, error::Error};
+
+/// The fields of a story we ask the API for.
+const STORY_FIELDS: &str = "id,title,description,user(name),tags,completed,mature,\
+                            language(name),createDate,modifyDate,parts(id,title,wordCount)";
+
+pub struct BuildWattpadAdapter;
+
+impl BuildAdapter for BuildWattpadAdapter {
+    fn build_async(&self, options: AdapterOptions) -> Box<dyn AsyncAdapter + 'static> {
+        Box::new(WattpadAdapter::new(options))
+    }
+}
+
+pub struct WattpadAdapter {
+    http: Http,
+    base: Url,
+}
+
+impl WattpadAdapter {
+    fn new(options: AdapterOptions) -> Self {
+        Self {
+            base: options.base_or("https://www.wattpad.com/"),
+            http: options.http,
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for WattpadAdapter {
+    fn name(&self) -> &'static str {
+        "wattpad"
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        let page = Url::parse(url)?;
+        let segments: Vec<_> = page
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty())
+            .collect();
+
+        match segments[..] {
+            ["story", story, ..] if leading_id(story).is_some() => {
+                let story = story_url(&self.base, leading_id(story).unwrap())?;
+                Ok(DirectoryStream::new(Some(story.into()), Metadata::new()))
+            }
+            // Parts live at the top level, and only the API knows which story they belong to.
+            [part] if leading_id(part).is_some() => {
+                let api = self.base.join(&format!(
+                    "api/v3/story_parts/{}?fields=groupId",
+                    leading_id(part).unwrap()
+                ))?;
+                let part: PartInfo = self.http.json(api.as_str()).await?;
+                let story = story_url(&self.base, &part.group_id)?;
+                Ok(DirectoryStream::new(Some(story.into()), Metadata::new()))
+            }
+            ["user", name, ..] => {
+                let api = self.base.join(&format!(
+                    "api/v3/users/{}/stories/published?fields=stories(id),nextUrl&limit=50",
+                    name
+                ))?;
+                let text = self.http.text(api.as_str()).await?;
+                let first = StoryList::listing(&self.base, &api, &text)?;
+                let mut directory = DirectoryStream::new(first.urls, Metadata::new());
+                if first.next.is_some() {
+                    let base = self.base.clone();
+                    directory = directory.with_paging(LinkPaging::new(
+                        self.http.clone(),
+                        api,
+                        first.next,
+                        move |page, text| StoryList::listing(&base, page, text),
+                    ));
+                }
+                Ok(directory)
+            }
+            _ => {
+                let text = self.http.text(url).await?;
+                let document = nipper::Document::from(&text);
+                let mut seen = HashSet::new();
+                let stories: Vec<_> = document
+                    .select("a[href*='/story/']")
+                    .iter()
+                    .filter_map(|link| resolve::link(&page, &link.attr("href")?))
+                    .filter_map(|url| {
+                        let story = url.path_segments()?.nth(1)?;
+                        story_url(&self.base, leading_id(story)?).ok()
+                    })
+                    .filter(|url| seen.insert(url.clone()))
+                    .map(String::from)
+                    .collect();
+                Ok(DirectoryStream::new(stories, Metadata::new()))
+            }
+        }
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let page = Url::parse(context.url())?;
+        let id = page
+            .path_segments()
+            .and_then(|mut segments| segments.nth(1))
+            .and_then(leading_id)
+            .ok_or_else(|| Error::NoStory(page.to_string()))?;
+        let api = self
+            .base
+            .join(&format!("api/v3/stories/{}?fields={}", id, STORY_FIELDS))?;
+        let mut story: Story = self.http.json(api.as_str()).await?;
+        // Parts without an id have no text we could ask for.
+        story.parts.retain(|part| !part.id.is_empty());
+
+        let mut meta = story.meta();
+        meta.insert(Meta::SourceUrl, Value::url(context.url()));
+
+        // The text of each part is an html fragment of its own.
+        let mut content = String::new();
+        let headings = story.parts.len() > 1;
+        for part in &story.parts {
+            let api = self
+                .base
+                .join(&format!("apiv2/?m=storytext&id={}", part.id))?;
+            let text = self.http.text(api.as_str()).await?;
+            if headings {
+                writeln!(content, "<h2>{}</h2>", escape(&part.title)).unwrap();
+            }
+            writeln!(content, "{}", text.trim()).unwrap();
+        }
+
+        let meta = context.merge(meta);
+        let mut buf = document_header(&meta);
+        buf.push_str(&content);
+
+        Ok(Document { meta, text: buf })
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Story {
+    #[serde(deserialize_with = "json::lenient")]
+    title: String,
+    #[serde(deserialize_with = "json::lenient")]
+    description: String,
+    #[serde(deserialize_with = "json::lenient")]
+    user: Named,
+    #[serde(deserialize_with = "json::list")]
+    tags: Vec<String>,
+    #[serde(deserialize_with = "json::lenient")]
+    completed: Option<bool>,
+    #[serde(deserialize_with = "json::lenient")]
+    mature: Option<bool>,
+    #[serde(deserialize_with = "json::lenient")]
+    language: Named,
+    #[serde(deserialize_with = "json::lenient")]
+    create_date: String,
+    #[serde(deserialize_with = "json::lenient")]
+    modify_date: String,
+    #[serde(deserialize_with = "json::list")]
+    parts: Vec<Part>,
+}
+
+impl Story {
+    fn meta(&self) -> Metadata {
+        let mut meta = Metadata::new();
+        for (key, text) in [
+            (Meta::Title, &self.title),
+            (Meta::Author, &self.user.name),
+            (Meta::Summary, &self.description),
+            (Meta::Language, &self.language.name),
+        ] {
+            if !text.trim().is_empty() {
+                meta.insert(key, text.trim());
+            }
+        }
+        for (key, date) in [
+            (Meta::PublicationDate, &self.create_date),
+            (Meta::LastUpdated, &self.modify_date),
+        ] {
+            if !date.is_empty() {
+                meta.insert(key, Value::date(date));
+            }
+        }
+        if !self.tags.is_empty() {
+            meta.insert(Meta::Tags, Value::list(&self.tags));
+        }
+        if let Some(completed) = self.completed {
+            meta.insert(Meta::Status, if completed { "complete" } else { "ongoing" });
+        }
+        if let Some(mature) = self.mature {
+            meta.insert(Meta::Rating, if mature { "mature" } else { "everyone" });
+        }
+
+        // Only count words if every part says how many it has.
+        let words: Option<i64> = self.parts.iter().map(|part| part.word_count).sum();
+        if let Some(words) = words.filter(|_| !self.parts.is_empty()) {
+            meta.insert(Meta::WordCount, Value::Integer(words));
+        }
+        meta
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Part {
+    #[serde(deserialize_with = "json::string")]
+    id: String,
+    #[serde(deserialize_with = "json::lenient")]
+    title: String,
+    #[serde(deserialize_with = "json::lenient")]
+    word_count: Option<i64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Named {
+    #[serde(deserialize_with = "json::lenient")]
+    name: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct PartInfo {
+    #[serde(deserialize_with = "json::string")]
+    group_id: String,
+}
+
+/// One page of a user's works.
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct StoryList {
+    #[serde(deserialize_with = "json::list")]
+    stories: Vec<StoryId>,
+    #[serde(deserialize_with = "json::lenient")]
+    next_url: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct StoryId {
+    #[serde(deserialize_with = "json::string")]
+    id: String,
+}
+
+impl StoryList {
+    /// Read a page of the list as a listing, with the urls of its stories.
+    fn listing(base: &Url, page: &Url, text: &str) -> Result<Listing> {
+        let list: StoryList = serde_json::from_str(text)?;
+        let urls = list
+            .stories
+            .iter()
+            .filter(|story| !story.id.is_empty())
+            .map(|story| Ok(story_url(base, &story.id)?.into()))
+            .collect::<Result<_>>()?;
+        let next = Some(&list.next_url)
+            .filter(|next| !next.is_empty())
+            .and_then(|next| page.join(next).ok());
+        Ok(Listing { urls, next })
+    }
+}
+
+fn story_url(base: &Url, id: &str) -> Result<Url> {
+    Ok(base.join(&format!("story/{}", id))?)
+}
+
+/// The id a story or part is known by, at the start of its slug, e.g. `12345-some-title`.
+fn leading_id(slug: &str) -> Option<&str> {
+    let end = slug
+        .find(|u: char| !u.is_ascii_digit())
+        .unwrap_or(slug.len());
+    Some(&slug[..end]).filter(|id| !id.is_empty())
+}
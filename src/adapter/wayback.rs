@@ -0,0 +1,132 @@
+//! Falling back to archived snapshots of documents that are no longer available.
+//!
+//! When a download fails because the document is gone (404 or 410) or the site cannot be
+//! reached, the archive's CDX api is asked for the most recent good snapshot of the document,
+//! and the same adapter downloads it again from that snapshot. Every request the adapter makes
+//! for the document, e.g. for further parts of a story, then goes to the archive as well.
+
+use std::sync::Arc;
+
+use super::prelude::*;
+use crate::error::Error;
+
+/// An archive with a Wayback Machine-compatible CDX api.
+#[derive(Clone, Debug)]
+pub struct Wayback {
+    cdx: Url,
+    web: Url,
+}
+
+impl Wayback {
+    /// The Internet Archive's Wayback Machine.
+    pub fn new() -> Self {
+        Self::with_origin(&Url::parse("https://web.archive.org/").unwrap())
+    }
+
+    /// An archive served from `origin`, with the CDX api at `cdx/search/cdx` and snapshots
+    /// under `web/`.
+    pub fn with_origin(origin: &Url) -> Self {
+        Self {
+            cdx: origin.join("cdx/search/cdx").unwrap(),
+            web: origin.join("web/").unwrap(),
+        }
+    }
+
+    /// The timestamp of the most recent successful snapshot of a url, if there is one.
+    pub async fn closest(&self, http: &Http, url: &str) -> Result<Option<String>> {
+        let mut query = self.cdx.clone();
+        query
+            .query_pairs_mut()
+            .append_pair("url", url)
+            .append_pair("output", "json")
+            .append_pair("fl", "timestamp")
+            .append_pair("filter", "statuscode:200")
+            .append_pair("limit", "-1");
+
+        // Rows of fields, preceded by a header row naming them.
+        let rows: Vec<Vec<String>> = serde_json::from_str(&http.text(query.as_str()).await?)?;
+        Ok(rows
+            .into_iter()
+            .skip(1)
+            .last()
+            .and_then(|row| row.into_iter().next()))
+    }
+
+    /// The prefix for the raw archived content of any url as of `timestamp`.
+    fn prefix(&self, timestamp: &str) -> String {
+        format!("{}{}id_/", self.web, timestamp)
+    }
+}
+
+impl Default for Wayback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An adapter which falls back to archived snapshots for documents it cannot retrieve.
+pub struct WaybackAdapter {
+    adapter: Box<dyn AsyncAdapter + 'static>,
+    builder: Arc<dyn BuildAdapter + 'static>,
+    options: AdapterOptions,
+    wayback: Wayback,
+}
+
+impl WaybackAdapter {
+    pub fn new(
+        builder: Arc<dyn BuildAdapter + 'static>,
+        options: AdapterOptions,
+        wayback: Wayback,
+    ) -> Self {
+        Self {
+            adapter: builder.build_async(options.clone()),
+            builder,
+            options,
+            wayback,
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncAdapter for WaybackAdapter {
+    fn name(&self) -> &str {
+        self.adapter.name()
+    }
+
+    async fn directory(&self, url: &str) -> Result<DirectoryStream> {
+        self.adapter.directory(url).await
+    }
+
+    async fn download(&self, context: DocumentUrl) -> Result<Document> {
+        let error = match self.adapter.download(context.clone()).await {
+            Err(e) if is_gone(&e) => e,
+            result => return result,
+        };
+
+        let timestamp = match self
+            .wayback
+            .closest(&self.options.http, context.url())
+            .await
+        {
+            Ok(Some(timestamp)) => timestamp,
+            _ => return Err(error),
+        };
+        let adapter = self.builder.build_async(AdapterOptions {
+            http: self.options.http.archived(self.wayback.prefix(&timestamp)),
+            ..self.options.clone()
+        });
+
+        let mut document = adapter.download(context).await?;
+        document.meta.insert(Meta::Snapshot, timestamp);
+        Ok(document)
+    }
+}
+
+/// Whether an error means the document is no longer available where we looked for it.
+fn is_gone(error: &Error) -> bool {
+    match error {
+        Error::Status(status, _) => *status == 404 || *status == 410,
+        Error::Reqwest(e) => e.is_connect() || e.is_timeout(),
+        _ => false,
+    }
+}
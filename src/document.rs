@@ -1,22 +1,211 @@
+pub mod text;
+pub mod value;
+
 use std::collections::HashMap;
 
+use chrono::NaiveDate;
+
+pub use value::Value;
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Meta {
     Author,
+    /// Site-defined categories; multi-valued.
+    Categories,
     Extension,
+    Language,
+    LastUpdated,
     Other(String),
     PublicationDate,
+    Rating,
+    Series,
+    /// Position within `Series`.
+    SeriesIndex,
+    /// Timestamp of the archived snapshot a document was retrieved from, e.g. "20200101000000".
+    Snapshot,
+    SourceUrl,
+    /// Completion status, e.g. "complete" or "ongoing".
+    Status,
+    Summary,
+    /// Tags, keywords or story codes; multi-valued.
+    Tags,
     Title,
+    WordCount,
+}
+
+impl Meta {
+    /// A stable name for this key, for use in exported metadata.
+    pub fn key(&self) -> &str {
+        match self {
+            Meta::Author => "author",
+            Meta::Categories => "categories",
+            Meta::Extension => "extension",
+            Meta::Language => "language",
+            Meta::LastUpdated => "last_updated",
+            Meta::Other(key) => key,
+            Meta::PublicationDate => "publication_date",
+            Meta::Rating => "rating",
+            Meta::Series => "series",
+            Meta::SeriesIndex => "series_index",
+            Meta::Snapshot => "snapshot",
+            Meta::SourceUrl => "source_url",
+            Meta::Status => "status",
+            Meta::Summary => "summary",
+            Meta::Tags => "tags",
+            Meta::Title => "title",
+            Meta::WordCount => "word_count",
+        }
+    }
+
+    /// Look up a key by the name given by `Meta::key`; unknown names become `Other`.
+    pub fn from_key(key: &str) -> Meta {
+        match key {
+            "author" => Meta::Author,
+            "categories" => Meta::Categories,
+            "extension" => Meta::Extension,
+            "language" => Meta::Language,
+            "last_updated" => Meta::LastUpdated,
+            "publication_date" => Meta::PublicationDate,
+            "rating" => Meta::Rating,
+            "series" => Meta::Series,
+            "series_index" => Meta::SeriesIndex,
+            "snapshot" => Meta::Snapshot,
+            "source_url" => Meta::SourceUrl,
+            "status" => Meta::Status,
+            "summary" => Meta::Summary,
+            "tags" => Meta::Tags,
+            "title" => Meta::Title,
+            "word_count" => Meta::WordCount,
+            other => Meta::Other(other.into()),
+        }
+    }
+
+    /// Parse scraped text into the type of value expected for this key.
+    ///
+    /// Lists are split on commas.
+    pub fn parse(&self, text: &str) -> Value {
+        match self {
+            Meta::Categories | Meta::Tags => Value::list(text.split(',')),
+            Meta::LastUpdated | Meta::PublicationDate => Value::date(text),
+            Meta::SeriesIndex | Meta::WordCount => Value::integer(text),
+            Meta::SourceUrl => Value::url(text),
+            _ => Value::Text(text.trim().into()),
+        }
+    }
+}
+
+/// A document's metadata.
+///
+/// Metadata comes from two places: the directory a document was found in (e.g. an author
+/// page) and the document itself. When the two are merged, values from the document take
+/// precedence, except that lists are combined.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata(HashMap<Meta, Value>);
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &Meta) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    pub fn contains(&self, key: &Meta) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Get a text or url value as a string.
+    pub fn text(&self, key: &Meta) -> Option<&str> {
+        self.0.get(key).and_then(Value::as_str)
+    }
+
+    pub fn date(&self, key: &Meta) -> Option<NaiveDate> {
+        match self.0.get(key)? {
+            Value::Date(date) => Some(*date),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, key: &Meta) -> Option<i64> {
+        match self.0.get(key)? {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Get the values of a list; a single text value is treated as a list of one.
+    pub fn list(&self, key: &Meta) -> Vec<&str> {
+        match self.0.get(key) {
+            Some(Value::List(values)) => values.iter().map(AsRef::as_ref).collect(),
+            Some(Value::Text(text)) => vec![text.as_ref()],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, key: Meta, value: impl Into<Value>) -> Option<Value> {
+        self.0.insert(key, value.into())
+    }
+
+    /// Insert a value only if the key is not already present.
+    pub fn insert_default(&mut self, key: Meta, value: impl Into<Value>) {
+        self.0.entry(key).or_insert_with(|| value.into());
+    }
+
+    pub fn remove(&mut self, key: &Meta) -> Option<Value> {
+        self.0.remove(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Meta, &Value)> {
+        self.0.iter()
+    }
+
+    /// Merge in metadata of higher precedence.
+    ///
+    /// Values from `other` replace our own, except where both sides hold lists; those are
+    /// combined, with our entries first and duplicates removed.
+    pub fn merge(&mut self, other: Metadata) {
+        for (key, value) in other.0 {
+            match (self.0.get_mut(&key), value) {
+                (Some(Value::List(ours)), Value::List(theirs)) => {
+                    for value in theirs {
+                        if !ours.contains(&value) {
+                            ours.push(value);
+                        }
+                    }
+                }
+                (_, value) => {
+                    self.0.insert(key, value);
+                }
+            }
+        }
+    }
+}
+
+impl FromIterator<(Meta, Value)> for Metadata {
+    fn from_iter<T: IntoIterator<Item = (Meta, Value)>>(iter: T) -> Self {
+        Metadata(iter.into_iter().collect())
+    }
 }
 
 pub struct Document {
-    pub meta: HashMap<Meta, String>,
+    pub meta: Metadata,
     pub text: String,
 }
 
 impl Document {
+    /// Create an html document from a plain-text story.
+    pub fn from_text(meta: Metadata, text: &str) -> Self {
+        let text = text::to_html(text, meta.text(&Meta::Title));
+        Document { meta, text }
+    }
+
     pub fn author(&self) -> Option<&str> {
-        self.meta.get(&Meta::Author).map(AsRef::as_ref)
+        self.meta.text(&Meta::Author)
+    }
+
+    pub fn categories(&self) -> Vec<&str> {
+        self.meta.list(&Meta::Categories)
     }
 
     pub fn content(&self) -> &str {
@@ -24,17 +213,58 @@ impl Document {
     }
 
     pub fn extension(&self) -> &str {
-        self.meta
-            .get(&Meta::Extension)
-            .map(AsRef::as_ref)
-            .unwrap_or("html")
+        self.meta.text(&Meta::Extension).unwrap_or("html")
+    }
+
+    pub fn publication_date(&self) -> Option<NaiveDate> {
+        self.meta.date(&Meta::PublicationDate)
     }
 
-    pub fn publication_date(&self) -> Option<&str> {
-        self.meta.get(&Meta::PublicationDate).map(AsRef::as_ref)
+    pub fn series(&self) -> Option<&str> {
+        self.meta.text(&Meta::Series)
+    }
+
+    pub fn source_url(&self) -> Option<&str> {
+        self.meta.text(&Meta::SourceUrl)
+    }
+
+    pub fn summary(&self) -> Option<&str> {
+        self.meta.text(&Meta::Summary)
+    }
+
+    pub fn tags(&self) -> Vec<&str> {
+        self.meta.list(&Meta::Tags)
     }
 
     pub fn title(&self) -> Option<&str> {
-        self.meta.get(&Meta::Title).map(AsRef::as_ref)
+        self.meta.text(&Meta::Title)
+    }
+
+    /// Why the document is incomplete, if an adapter had to cut it short, e.g. at a limit on
+    /// the number of chapters.
+    pub fn truncated(&self) -> Option<&str> {
+        self.meta.text(&Meta::Other("truncated".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Meta, Metadata, Value};
+
+    #[test]
+    fn merge_precedence() {
+        let mut directory = Metadata::new();
+        directory.insert(Meta::Author, "Directory Author");
+        directory.insert(Meta::Series, "Series");
+        directory.insert(Meta::Tags, Value::list(["a", "b"]));
+
+        let mut document = Metadata::new();
+        document.insert(Meta::Author, "Document Author");
+        document.insert(Meta::Tags, Value::list(["b", "c"]));
+
+        directory.merge(document);
+        assert_eq!(directory.text(&Meta::Author), Some("Document Author"));
+        assert_eq!(directory.text(&Meta::Series), Some("Series"));
+        assert_eq!(directory.list(&Meta::Tags), ["a", "b", "c"]);
     }
 }
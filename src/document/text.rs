@@ -0,0 +1,402 @@
+//! Conversion of plain-text stories into html.
+//!
+//! Text archives mostly carry stories hard-wrapped at 72 columns or so, with paragraphs
+//! separated by blank lines or (less helpfully) by indentation alone. Most also open with a
+//! block of title/author/copyright lines. We try to recover all of that structure so that a
+//! text story reads the same as one scraped from an html page.
+
+use std::{fmt::Write, sync::OnceLock};
+
+use regex::Regex;
+
+use super::{Meta, Metadata};
+
+static STYLE: &str = "body { max-width: 40em; margin: 2em auto; padding: 0 1em; \
+font-family: Georgia, serif; line-height: 1.5; }
+header { text-align: center; margin-bottom: 2em; }
+header p { margin: 0.25em 0; }
+hr { border: none; text-align: center; margin: 1.5em 0; }
+hr::after { content: \"* * *\"; }";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Block {
+    Header(Vec<String>),
+    Heading(String),
+    Paragraph(String),
+    SceneBreak,
+}
+
+/// Convert a plain-text story into a complete html document.
+pub fn to_html(text: &str, title: Option<&str>) -> String {
+    html_document(&to_html_fragment(text), title)
+}
+
+/// Convert a plain-text story posted in parts into a single html document, with a heading
+/// before each part.
+pub fn parts_to_html<'a>(
+    parts: impl IntoIterator<Item = (&'a str, &'a str)>,
+    title: Option<&str>,
+) -> String {
+    let mut body = String::new();
+    for (heading, text) in parts {
+        writeln!(body, "<h2>{}</h2>", escape(heading)).unwrap();
+        body += &to_html_fragment(text);
+    }
+    html_document(&body, title)
+}
+
+fn html_document(body: &str, title: Option<&str>) -> String {
+    let mut buf = String::new();
+    writeln!(
+        buf,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    )
+    .unwrap();
+    if let Some(title) = title {
+        writeln!(buf, "<title>{}</title>", escape(title)).unwrap();
+    }
+    writeln!(buf, "<style>\n{}\n</style>\n</head>\n<body>", STYLE).unwrap();
+    buf += body;
+    buf += "</body>\n</html>\n";
+    buf
+}
+
+/// Convert a plain-text story into html body content, without the surrounding document.
+pub fn to_html_fragment(text: &str) -> String {
+    let mut buf = String::new();
+    for block in parse(text) {
+        match block {
+            Block::Header(lines) => {
+                let lines: Vec<_> = lines.iter().map(|line| escape(line)).collect();
+                writeln!(buf, "<header>\n<p>{}</p>\n</header>", lines.join("<br>\n")).unwrap();
+            }
+            Block::Heading(text) => writeln!(buf, "<h2>{}</h2>", escape(&text)).unwrap(),
+            Block::Paragraph(text) => writeln!(buf, "<p>{}</p>", escape(&text)).unwrap(),
+            Block::SceneBreak => buf += "<hr>\n",
+        }
+    }
+    buf
+}
+
+/// Read metadata from "Key: value" lines in a story's header block.
+pub fn header_meta(text: &str) -> Metadata {
+    static FIELD: OnceLock<Regex> = OnceLock::new();
+    let field = regex(&FIELD, r"^([A-Za-z][\w ]{0,20}):\s*(.+)$");
+    let mut meta = Metadata::new();
+
+    let lines = parse(text)
+        .into_iter()
+        .take(1)
+        .flat_map(|block| match block {
+            Block::Header(lines) => lines,
+            _ => Vec::new(),
+        });
+
+    for line in lines {
+        let (key, value) = match field.captures(&line) {
+            Some(cx) => (cx[1].to_ascii_lowercase(), cx[2].to_owned()),
+            None => continue,
+        };
+        let key = match key.as_str() {
+            "title" => Meta::Title,
+            "author" | "by" | "written by" => Meta::Author,
+            "keywords" | "codes" | "tags" => Meta::Tags,
+            "category" | "categories" => Meta::Categories,
+            "summary" | "synopsis" | "description" => Meta::Summary,
+            "date" | "posted" | "published" => Meta::PublicationDate,
+            "updated" | "last updated" => Meta::LastUpdated,
+            "series" => Meta::Series,
+            "rating" | "rated" => Meta::Rating,
+            "language" => Meta::Language,
+            "status" => Meta::Status,
+            _ => continue,
+        };
+        let value = key.parse(&value);
+        meta.insert_default(key, value);
+    }
+
+    meta
+}
+
+/// Split the mail headers off a story that was posted by mail, as on archives fed from
+/// mailing lists and newsgroups.
+///
+/// Header names are lowercased. Text which does not open with a `From:`, `Subject:` or `Date:`
+/// header is returned as it is.
+pub fn split_mail_headers(text: &str) -> (Vec<(String, String)>, &str) {
+    static FIELD: OnceLock<Regex> = OnceLock::new();
+    let field = regex(&FIELD, r"^([A-Za-z][A-Za-z0-9-]*):[ \t]*(.*)$");
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut consumed = 0;
+
+    // Headers end at the first blank line.
+    for line in text.split_inclusive('\n') {
+        consumed += line.len();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        match (field.captures(line), headers.last_mut()) {
+            (Some(cx), _) => headers.push((cx[1].to_ascii_lowercase(), cx[2].trim().to_owned())),
+            // Long headers are folded onto indented lines.
+            (None, Some((_, value))) if line.starts_with([' ', '\t']) => {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            _ => return (Vec::new(), text),
+        }
+    }
+
+    let is_mail = headers
+        .iter()
+        .any(|(name, _)| name == "from" || name == "subject" || name == "date");
+    if is_mail {
+        (headers, &text[consumed..])
+    } else {
+        (Vec::new(), text)
+    }
+}
+
+/// A pattern compiled on first use and kept for every later call.
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+fn parse(text: &str) -> Vec<Block> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let lines: Vec<_> = text.lines().map(|line| line.trim_end()).collect();
+    let width = wrap_width(&lines);
+    static SCENE_BREAK: OnceLock<Regex> = OnceLock::new();
+    static HEADING: OnceLock<Regex> = OnceLock::new();
+    let scene_break = regex(&SCENE_BREAK, r"^([*\-_=~#+]\s*){3,}$|^#$");
+    let heading = regex(
+        &HEADING,
+        r"(?i)^(chapter|part|book|prologue|epilogue|interlude)\b.{0,50}$",
+    );
+
+    let mut blocks = Vec::new();
+    let mut in_front_matter = true;
+
+    for group in lines.split(|line| line.trim().is_empty()) {
+        if group.is_empty() {
+            continue;
+        }
+
+        if group.len() == 1 {
+            let line = group[0].trim();
+            if scene_break.is_match(line) {
+                blocks.push(Block::SceneBreak);
+                in_front_matter = false;
+                continue;
+            }
+            if heading.is_match(line) {
+                blocks.push(Block::Heading(line.into()));
+                in_front_matter = false;
+                continue;
+            }
+        }
+
+        if in_front_matter && is_header(group, width) {
+            let lines = group.iter().map(|line| line.trim().to_owned());
+            match blocks.last_mut() {
+                Some(Block::Header(header)) => header.extend(lines),
+                _ => blocks.push(Block::Header(lines.collect())),
+            }
+            continue;
+        }
+
+        in_front_matter = false;
+        blocks.extend(paragraphs(group, width).into_iter().map(Block::Paragraph));
+    }
+
+    blocks
+}
+
+/// Estimate the column at which the text was wrapped.
+///
+/// The longest lines are usually a handful of outliers (urls, ascii art) so we take a high
+/// percentile rather than the maximum.
+fn wrap_width(lines: &[&str]) -> usize {
+    let mut lengths: Vec<_> = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .filter(|&len| len > 0)
+        .collect();
+    if lengths.is_empty() {
+        return 72;
+    }
+    lengths.sort_unstable();
+    lengths[lengths.len() * 9 / 10]
+}
+
+/// A header block is a short run of short lines at the top of the story: a title, a byline,
+/// a copyright notice, "Keywords: ..." and so forth.
+fn is_header(group: &[&str], width: usize) -> bool {
+    static FIELD: OnceLock<Regex> = OnceLock::new();
+    let field = regex(&FIELD, r"^\s*[A-Za-z][\w ]{0,20}:\s");
+    group.len() <= 8
+        && group.iter().all(|line| {
+            let len = line.trim().chars().count();
+            let indent = line.chars().take_while(|u| u.is_whitespace()).count();
+            field.is_match(line) || indent >= 8 || len * 3 < width * 2
+        })
+        // A single long sentence is the first paragraph, not a header.
+        && !(group.len() == 1 && group[0].trim().ends_with('.') && group[0].len() * 2 > width)
+}
+
+/// Unwrap a group of lines into paragraphs.
+///
+/// Most text stories separate paragraphs with blank lines, in which case the whole group is a
+/// single paragraph. Some only indent the first line of each paragraph, and some just leave
+/// the last line of a paragraph short; we split on either.
+fn paragraphs(group: &[&str], width: usize) -> Vec<String> {
+    let indented = group
+        .iter()
+        .skip(1)
+        .filter(|line| line.starts_with([' ', '\t']))
+        .count();
+    let splits_on_indent = indented > 0 && indented < group.len() - 1;
+
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    let mut previous: Option<&str> = None;
+
+    for &line in group {
+        if let Some(previous) = previous {
+            let indent_break = splits_on_indent && line.starts_with([' ', '\t']);
+            let short_break = ends_sentence(previous)
+                && previous.trim().chars().count() * 4 < width * 3
+                && starts_sentence(line);
+            if indent_break || short_break {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+        }
+
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current += line.trim();
+        previous = Some(line);
+    }
+
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+    paragraphs
+}
+
+fn ends_sentence(line: &str) -> bool {
+    line.trim_end()
+        .ends_with(['.', '!', '?', '"', '\'', '\u{201d}', '\u{2019}'])
+}
+
+fn starts_sentence(line: &str) -> bool {
+    line.trim_start()
+        .chars()
+        .next()
+        .map(|u| u.is_uppercase() || u == '"' || u == '\'' || u == '\u{201c}')
+        .unwrap_or_default()
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Block, Meta};
+
+    #[test]
+    fn unwraps_hard_wrapped_paragraphs() {
+        let text = "The first line of a paragraph that goes on for a while and then\n\
+            wraps at seventy-two columns, the way text archives tend to do it,\n\
+            until it stops.\n\
+            \n\
+            A second paragraph.\n";
+        let blocks = super::parse(text);
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Paragraph("The first line of a paragraph that goes on for a while and then wraps at seventy-two columns, the way text archives tend to do it, until it stops.".into()),
+                Block::Paragraph("A second paragraph.".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_indented_paragraphs() {
+        let text = "    She opened the door and stepped out into the hall, where the\n\
+            light was dim and the air smelled of dust.\n\
+            \x20   Nobody answered when she called out, so she kept walking toward\n\
+            the stairs.\n";
+        let blocks = super::parse(text);
+        assert_eq!(blocks.len(), 2);
+    }
+
+    #[test]
+    fn detects_header_and_scene_breaks() {
+        let text = "Title: A Story\n\
+            Author: Somebody\n\
+            \n\
+            It was a dark and stormy night, and the rain fell in torrents except at\n\
+            occasional intervals.\n\
+            \n\
+            * * *\n\
+            \n\
+            Chapter 2\n\
+            \n\
+            Morning came.\n";
+        let blocks = super::parse(text);
+        assert_eq!(
+            blocks[0],
+            Block::Header(vec!["Title: A Story".into(), "Author: Somebody".into()])
+        );
+        assert_eq!(blocks[2], Block::SceneBreak);
+        assert_eq!(blocks[3], Block::Heading("Chapter 2".into()));
+    }
+
+    #[test]
+    fn reads_header_fields() {
+        let text = "Title: A Story\n\
+            Author: Somebody\n\
+            Keywords: mf, cons\n\
+            \n\
+            It was a dark and stormy night, and the rain fell in torrents except at\n\
+            occasional intervals.\n";
+        let meta = super::header_meta(text);
+        assert_eq!(meta.text(&Meta::Title), Some("A Story"));
+        assert_eq!(meta.text(&Meta::Author), Some("Somebody"));
+        assert_eq!(meta.list(&Meta::Tags), ["mf", "cons"]);
+    }
+
+    #[test]
+    fn splits_mail_headers() {
+        let text = "Date: Tue, 4 Jan 2022 10:12:33 -0800\r\n\
+            From: Some Author <someone@example.com>\r\n\
+            Subject: The Boy Next Door 3\r\n\
+            \x20 (a long subject)\r\n\
+            \r\n\
+            It was a dark and stormy night.\r\n";
+        let (headers, rest) = super::split_mail_headers(text);
+        assert_eq!(headers.len(), 3);
+        assert_eq!(
+            headers[1],
+            ("from".into(), "Some Author <someone@example.com>".into())
+        );
+        assert_eq!(headers[2].1, "The Boy Next Door 3 (a long subject)");
+        assert_eq!(rest, "It was a dark and stormy night.\r\n");
+
+        let text = "Title: A Story\n\nIt was a dark and stormy night.\n";
+        assert_eq!(super::split_mail_headers(text), (Vec::new(), text));
+    }
+
+    #[test]
+    fn escapes_markup() {
+        let html = super::to_html("Tom & <Jerry>", Some("T&J"));
+        assert!(html.contains("<title>T&amp;J</title>"));
+        assert!(html.contains("Tom &amp; &lt;Jerry&gt;"));
+    }
+}
@@ -0,0 +1,239 @@
+//! Typed metadata values and the parsing helpers used to produce them.
+
+use std::fmt::{self, Display};
+
+use chrono::{DateTime, Datelike, NaiveDate};
+use serde::{Serialize, Serializer};
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Date(NaiveDate),
+    Integer(i64),
+    List(Vec<String>),
+    Text(String),
+    Url(Url),
+}
+
+impl Value {
+    /// Parse a date in any of the formats used by supported sites, falling back to the
+    /// original text when the format is not recognized.
+    pub fn date(text: &str) -> Value {
+        parse_date(text)
+            .map(Value::Date)
+            .unwrap_or_else(|| Value::Text(text.trim().into()))
+    }
+
+    /// Parse a count such as "45,123" or "12.5k", falling back to the original text.
+    pub fn integer(text: &str) -> Value {
+        parse_integer(text)
+            .map(Value::Integer)
+            .unwrap_or_else(|| Value::Text(text.trim().into()))
+    }
+
+    /// Build a list from individual values, dropping empty entries.
+    pub fn list<T: AsRef<str>>(values: impl IntoIterator<Item = T>) -> Value {
+        Value::List(
+            values
+                .into_iter()
+                .map(|value| value.as_ref().trim().to_owned())
+                .filter(|value| !value.is_empty())
+                .collect(),
+        )
+    }
+
+    /// Parse a url, falling back to the original text.
+    pub fn url(text: &str) -> Value {
+        Url::parse(text.trim())
+            .map(Value::Url)
+            .unwrap_or_else(|_| Value::Text(text.trim().into()))
+    }
+
+    /// Borrow a text or url value as a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            Value::Url(url) => Some(url.as_str()),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.into())
+    }
+}
+
+impl From<Vec<String>> for Value {
+    fn from(v: Vec<String>) -> Self {
+        Value::List(v)
+    }
+}
+
+impl From<NaiveDate> for Value {
+    fn from(v: NaiveDate) -> Self {
+        Value::Date(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Integer(v)
+    }
+}
+
+impl From<Url> for Value {
+    fn from(v: Url) -> Self {
+        Value::Url(v)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Date(date) => date.format("%Y-%m-%d").fmt(f),
+            Value::Integer(n) => n.fmt(f),
+            Value::List(values) => values.join(", ").fmt(f),
+            Value::Text(text) => text.fmt(f),
+            Value::Url(url) => url.fmt(f),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Integer(n) => serializer.serialize_i64(*n),
+            Value::List(values) => values.serialize(serializer),
+            value => serializer.collect_str(value),
+        }
+    }
+}
+
+/// Formats seen on supported sites, most specific first.
+///
+/// chrono's `%Y` also accepts a two-digit year, so `parse_date` skips years below 100 to let
+/// the `%y` formats match those.
+static DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%m/%d/%Y",
+    "%m/%d/%y",
+    "%m-%d-%Y",
+    "%m-%d-%y",
+    "%d %B %Y",
+    "%d %b %Y",
+    "%B %d, %Y",
+    "%b %d, %Y",
+    "%B %d %Y",
+    "%b %d %Y",
+    "%A, %B %d, %Y",
+    "%d.%m.%Y",
+];
+
+/// Parse a date as written by one of the supported sites.
+///
+/// Handles ISO dates, US-style numeric dates, written-out month names, RFC 2822 (as found in
+/// the email-style headers of text archives) and RFC 3339 timestamps.
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim().trim_end_matches('.');
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.date_naive());
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(text) {
+        return Some(date.date_naive());
+    }
+
+    // Strip ordinal suffixes, e.g. "March 3rd, 2021".
+    let text = ordinal_suffixes(text);
+    DATE_FORMATS
+        .iter()
+        .filter_map(|format| NaiveDate::parse_from_str(&text, format).ok())
+        .find(|date| date.year() >= 100)
+        .or_else(|| {
+            // ISO timestamps with a time but no offset; keep only the date part.
+            text.get(..10)
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        })
+}
+
+/// Parse a count as written by one of the supported sites: "45,123", "1 234" or "12.5k".
+pub fn parse_integer(text: &str) -> Option<i64> {
+    let text: String = text
+        .trim()
+        .chars()
+        .filter(|u| *u != ',' && !u.is_whitespace())
+        .collect();
+    let (number, scale) = match text.strip_suffix(['k', 'K']) {
+        Some(number) => (number, 1000.0),
+        None => (text.as_str(), 1.0),
+    };
+    if scale == 1.0 {
+        number.parse().ok()
+    } else {
+        number
+            .parse::<f64>()
+            .ok()
+            .map(|n| (n * scale).round() as i64)
+    }
+}
+
+fn ordinal_suffixes(text: &str) -> String {
+    let mut buf = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, u)) = chars.next() {
+        buf.push(u);
+        if u.is_ascii_digit() {
+            let rest = &text[idx + 1..];
+            if ["st", "nd", "rd", "th"]
+                .iter()
+                .any(|suffix| rest.starts_with(suffix))
+                && !rest[2..].starts_with(|u: char| u.is_alphabetic())
+            {
+                chars.next();
+                chars.next();
+            }
+        }
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    #[test]
+    fn parse_date() {
+        let expected = NaiveDate::from_ymd_opt(2021, 3, 3);
+        for text in [
+            "2021-03-03",
+            "03/03/2021",
+            "03/03/21",
+            "03-03-21",
+            "3 March 2021",
+            "Mar 3, 2021",
+            "March 3rd, 2021",
+            "Wed, 3 Mar 2021 10:15:00 -0500",
+            "2021-03-03T10:15:00Z",
+            "2021-03-03 10:15",
+        ] {
+            assert_eq!(expected, super::parse_date(text), "{}", text);
+        }
+        assert_eq!(None, super::parse_date("sometime last year"));
+    }
+
+    #[test]
+    fn parse_integer() {
+        assert_eq!(Some(45123), super::parse_integer("45,123"));
+        assert_eq!(Some(12500), super::parse_integer("12.5k"));
+        assert_eq!(Some(7), super::parse_integer(" 7 "));
+        assert_eq!(None, super::parse_integer("many"));
+    }
+}
@@ -1,11 +1,29 @@
-use std::{fmt::Display, io};
+use std::{fmt::Display, io, path::PathBuf};
 
 #[derive(Debug)]
 pub enum Error {
     BadUrl(url::ParseError),
+    /// A site answered with a challenge for suspected bots instead of the page.
+    Challenge(String),
     Io(io::Error),
+    Json(serde_json::Error),
+    /// Logging in to a site failed.
+    Login(String),
+    /// A document is only available in full to members of its site.
+    LoginRequired(String),
     MissingDomain(String),
+    /// A story has no chapters newer than those retrieved before.
+    NoNewChapters(String),
+    /// A url that should lead to a story has none there, e.g. after the site changed its
+    /// layout.
+    NoStory(String),
+    NotRecorded(String),
+    Recipe(PathBuf, String),
     Reqwest(reqwest::Error),
+    Script(PathBuf, String),
+    /// A setting has a value its adapter can't use.
+    Setting(String, String),
+    Status(u16, String),
     UnknownDomain(String),
 }
 
@@ -21,6 +39,12 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(v: serde_json::Error) -> Self {
+        Self::Json(v)
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(v: reqwest::Error) -> Self {
         Self::Reqwest(v)
@@ -31,8 +55,19 @@ impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::BadUrl(e) => e.fmt(f),
+            Error::Challenge(url) => write!(f, "blocked by a bot challenge: {}", url),
             Error::Io(e) => e.fmt(f),
+            Error::Json(e) => e.fmt(f),
+            Error::Login(host) => write!(f, "login failed: {}", host),
+            Error::LoginRequired(url) => write!(f, "login required: {}", url),
             Error::MissingDomain(value) => write!(f, "missing domain: {}", value),
+            Error::NoNewChapters(url) => write!(f, "no new chapters: {}", url),
+            Error::NoStory(url) => write!(f, "no story found at {}", url),
+            Error::NotRecorded(url) => write!(f, "no recorded response for {}", url),
+            Error::Recipe(path, e) => write!(f, "bad recipe {}: {}", path.display(), e),
+            Error::Script(path, e) => write!(f, "script {}: {}", path.display(), e),
+            Error::Setting(key, e) => write!(f, "bad setting {}: {}", key, e),
+            Error::Status(status, url) => write!(f, "http status {}: {}", status, url),
             Error::UnknownDomain(value) => write!(f, "unknown domain: {}", value),
             Error::Reqwest(e) => e.fmt(f),
         }
@@ -0,0 +1,18 @@
+//! Metadata written alongside retrieved documents.
+
+pub mod calibre;
+pub mod json;
+pub mod opf;
+
+use sha2::{Digest, Sha256};
+
+/// Hash document content for use in exported metadata.
+pub fn content_hash(content: &str) -> String {
+    let digest = Sha256::digest(content.as_bytes());
+    let mut hash = String::with_capacity(7 + digest.len() * 2);
+    hash += "sha256:";
+    for byte in digest {
+        hash += &format!("{:02x}", byte);
+    }
+    hash
+}
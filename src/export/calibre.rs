@@ -0,0 +1,78 @@
+//! Calibre library layout: `Author/Title (id)/Title - Author.ext` with a `metadata.opf`.
+
+use std::{fs, path::PathBuf};
+
+use regex::Regex;
+
+use crate::Result;
+
+pub struct Library {
+    root: PathBuf,
+    next_id: u64,
+    id_pattern: Regex,
+}
+
+impl Library {
+    /// Open a library rooted at `root`, continuing the id sequence of any books already there.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        let id_pattern = Regex::new(r" \((\d+)\)$").unwrap();
+        let mut max_id = 0;
+
+        if root.exists() {
+            for author in fs::read_dir(&root)? {
+                let author = author?.path();
+                if !author.is_dir() {
+                    continue;
+                }
+                for book in fs::read_dir(&author)? {
+                    if let Some(id) = book?
+                        .file_name()
+                        .to_str()
+                        .and_then(|name| parse_id(&id_pattern, name))
+                    {
+                        max_id = max_id.max(id);
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            root,
+            next_id: max_id + 1,
+            id_pattern,
+        })
+    }
+
+    /// Get the directory and id for a book, reusing the directory of an existing book with the
+    /// same author and title.
+    pub fn book(&mut self, author: &str, title: &str) -> Result<(PathBuf, u64)> {
+        let author_dir = self.root.join(author);
+        if author_dir.exists() {
+            for book in fs::read_dir(&author_dir)? {
+                let book = book?;
+                let name = book.file_name();
+                let name = match name.to_str() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                if let Some(id) = parse_id(&self.id_pattern, name) {
+                    if name.strip_suffix(&format!(" ({})", id)) == Some(title) {
+                        return Ok((book.path(), id));
+                    }
+                }
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        Ok((author_dir.join(format!("{} ({})", title, id)), id))
+    }
+}
+
+fn parse_id(pattern: &Regex, name: &str) -> Option<u64> {
+    pattern
+        .captures(name)
+        .and_then(|cx| cx.get(1))
+        .and_then(|cx| cx.as_str().parse().ok())
+}
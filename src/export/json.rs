@@ -0,0 +1,49 @@
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    document::{Document, Value},
+    Result,
+};
+
+/// A metadata record describing one retrieved document.
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    pub url: &'a str,
+    pub adapter: &'a str,
+    pub path: &'a Path,
+    pub hash: String,
+    pub meta: BTreeMap<&'a str, &'a Value>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(url: &'a str, adapter: &'a str, path: &'a Path, document: &'a Document) -> Self {
+        Self {
+            url,
+            adapter,
+            path,
+            hash: super::content_hash(document.content()),
+            meta: document
+                .meta
+                .iter()
+                .map(|(key, value)| (key.key(), value))
+                .collect(),
+        }
+    }
+
+    /// Write the record as a pretty-printed sidecar file.
+    pub fn write_sidecar(&self, path: &Path) -> Result<()> {
+        let mut file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)?;
+        Ok(())
+    }
+
+    /// Write the record as a single line of newline-delimited json.
+    pub fn write_line(&self, mut writer: impl Write) -> Result<()> {
+        serde_json::to_writer(&mut writer, self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! Open Packaging Format metadata, as read by Calibre.
+//!
+//! Calibre picks up a `metadata.opf` sitting next to a book when importing from folders, and
+//! the same file is what it writes into each book directory of its own libraries.
+
+use std::fmt::Write;
+
+use crate::document::{text::escape, Document, Meta};
+
+/// Render an OPF 2.0 package document describing `document`.
+///
+/// `id` is written as the Calibre book id when laying out a library.
+pub fn render(document: &Document, source: &str, id: Option<u64>) -> String {
+    let mut buf = String::new();
+    buf += "<?xml version='1.0' encoding='utf-8'?>\n";
+    buf += "<package xmlns=\"http://www.idpf.org/2007/opf\" unique-identifier=\"source_id\" version=\"2.0\">\n";
+    buf += "    <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n";
+
+    if let Some(id) = id {
+        element(
+            &mut buf,
+            "dc:identifier opf:scheme=\"calibre\" id=\"calibre_id\"",
+            &id.to_string(),
+        );
+    }
+    element(
+        &mut buf,
+        "dc:identifier opf:scheme=\"url\" id=\"source_id\"",
+        source,
+    );
+    element(&mut buf, "dc:title", document.title().unwrap_or("unknown"));
+    if let Some(author) = document.author() {
+        element(&mut buf, "dc:creator opf:role=\"aut\"", author);
+    }
+    element(
+        &mut buf,
+        "dc:contributor opf:role=\"bkp\"",
+        concat!("klit (", env!("CARGO_PKG_VERSION"), ")"),
+    );
+    if let Some(date) = document.meta.get(&Meta::PublicationDate) {
+        element(&mut buf, "dc:date", &date.to_string());
+    }
+    element(
+        &mut buf,
+        "dc:source",
+        document.source_url().unwrap_or(source),
+    );
+    if let Some(summary) = document.summary() {
+        element(&mut buf, "dc:description", summary);
+    }
+    if let Some(language) = document.meta.get(&Meta::Language) {
+        element(&mut buf, "dc:language", &language.to_string());
+    }
+
+    // Calibre only knows tags, so categories land there as well.
+    for tag in document.tags().into_iter().chain(document.categories()) {
+        element(&mut buf, "dc:subject", tag);
+    }
+
+    if let Some(series) = document.series() {
+        calibre_meta(&mut buf, "series", series);
+        if let Some(index) = document.meta.get(&Meta::SeriesIndex) {
+            calibre_meta(&mut buf, "series_index", &index.to_string());
+        }
+    }
+
+    buf += "    </metadata>\n    <guide/>\n</package>\n";
+    buf
+}
+
+fn element(buf: &mut String, tag: &str, content: &str) {
+    let name = tag.split(' ').next().unwrap_or(tag);
+    writeln!(buf, "        <{}>{}</{}>", tag, escape(content), name).unwrap();
+}
+
+fn calibre_meta(buf: &mut String, name: &str, content: &str) {
+    writeln!(
+        buf,
+        "        <meta name=\"calibre:{}\" content=\"{}\"/>",
+        name,
+        escape(content)
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::{Document, Meta, Metadata, Value};
+
+    #[test]
+    fn maps_metadata() {
+        let mut meta = Metadata::new();
+        meta.insert(Meta::Title, "Jack & Jill");
+        meta.insert(Meta::Author, "Anon");
+        meta.insert(Meta::Tags, Value::list(["hills", "water"]));
+        meta.insert(Meta::Series, "Rhymes");
+        meta.insert(Meta::SeriesIndex, 2);
+        meta.insert(Meta::PublicationDate, Value::date("March 3, 2021"));
+        let document = Document {
+            meta,
+            text: String::new(),
+        };
+
+        let opf = super::render(&document, "https://example.com/?a=1&b=2", Some(7));
+        assert!(opf.contains("<dc:title>Jack &amp; Jill</dc:title>"));
+        assert!(opf.contains("<dc:creator opf:role=\"aut\">Anon</dc:creator>"));
+        assert!(opf.contains("<dc:subject>hills</dc:subject>"));
+        assert!(opf.contains("<dc:subject>water</dc:subject>"));
+        assert!(opf.contains("<meta name=\"calibre:series\" content=\"Rhymes\"/>"));
+        assert!(opf.contains("<meta name=\"calibre:series_index\" content=\"2\"/>"));
+        assert!(opf.contains(">7</dc:identifier>"));
+        assert!(opf.contains("<dc:date>2021-03-03</dc:date>"));
+        assert!(opf.contains("<dc:source>https://example.com/?a=1&amp;b=2</dc:source>"));
+    }
+}
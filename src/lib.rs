@@ -0,0 +1,362 @@
+//! Story retrieval for text and html archives.
+//!
+//! klit resolves a url to an adapter for the site it belongs to, asks the adapter for the
+//! directory of documents behind that url (an author page, a listing, or a single story) and
+//! downloads each of them in turn.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! let klit = klit::Klit::builder().wait(Duration::from_secs(2)).build();
+//! for document in klit.documents("https://www.asstr.org/files/Authors/Somebody/")? {
+//!     let document = document?;
+//!     println!("{:?}: {} bytes", document.title(), document.content().len());
+//! }
+//! # Ok::<(), klit::Error>(())
+//! ```
+//!
+//! Async callers can use `Klit::stream` instead, which yields the same documents as a
+//! `Stream`; the blocking api is a thin wrapper over it.
+
+pub mod adapter;
+pub mod document;
+pub mod error;
+pub mod export;
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{stream::BoxStream, Stream, StreamExt};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::{
+    adapter::{
+        http::Http, Adapter, AdapterOptions, AsyncAdapter, Blocking, BuildAdapter,
+        BuildRecipeAdapter, BuildScriptAdapter, DirectoryStream, Recipe, Script, Wayback,
+        WaybackAdapter,
+    },
+    document::{Meta, Value},
+};
+
+pub use crate::{
+    document::{Document, Metadata},
+    error::Error,
+};
+
+pub type Result<T, E = error::Error> = std::result::Result<T, E>;
+
+/// Entry point for retrieving documents.
+pub struct Klit {
+    adapters: HashMap<String, Arc<dyn BuildAdapter + 'static>>,
+    /// Hosts served by the adapter registered for another host, e.g. mirrors.
+    aliases: HashMap<String, String>,
+    bases: HashMap<String, Url>,
+    http: Http,
+    settings: Arc<HashMap<String, String>>,
+    wait: Option<Duration>,
+    wayback: Option<Wayback>,
+}
+
+impl Klit {
+    /// Create an instance with all built-in adapters and no wait between requests.
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> KlitBuilder {
+        KlitBuilder {
+            adapters: adapter::builtin()
+                .into_iter()
+                .map(|(host, adapter)| (host.to_owned(), adapter.into()))
+                .collect(),
+            aliases: HashMap::new(),
+            bases: HashMap::new(),
+            http: Http::new(),
+            overrides: HashMap::new(),
+            settings: HashMap::new(),
+            timeout: None,
+            wait: None,
+            wayback: None,
+        }
+    }
+
+    /// Build the adapter for the site a url belongs to.
+    pub fn adapter(&self, url: &str) -> Result<Box<dyn Adapter + 'static>> {
+        if self.wayback.is_some() {
+            return Ok(Box::new(Blocking::new(self.async_adapter(url)?)));
+        }
+        let (builder, options) = self.adapter_builder(url)?;
+        Ok(builder.build(options))
+    }
+
+    /// Build the async adapter for the site a url belongs to.
+    pub fn async_adapter(&self, url: &str) -> Result<Box<dyn AsyncAdapter + 'static>> {
+        let (builder, options) = self.adapter_builder(url)?;
+        Ok(match &self.wayback {
+            Some(wayback) => Box::new(WaybackAdapter::new(
+                builder.clone(),
+                options,
+                wayback.clone(),
+            )),
+            None => builder.build_async(options),
+        })
+    }
+
+    /// Retrieve each document found at a url.
+    ///
+    /// Errors for individual documents are yielded in place of those documents; the remaining
+    /// documents can still be retrieved. This must not be called from within an async
+    /// context; use `stream` instead.
+    pub fn documents(&self, url: &str) -> Result<Documents> {
+        let runtime = adapter::runtime();
+        let stream = runtime.block_on(self.stream(url))?;
+        Ok(Documents { stream, runtime })
+    }
+
+    /// Retrieve each document found at a url, as a stream.
+    ///
+    /// As with `documents`, errors for individual documents are yielded in their place.
+    pub async fn stream(&self, url: &str) -> Result<DocumentStream> {
+        let adapter = self.async_adapter(url)?;
+        let directory = adapter.directory(url).await?;
+        Ok(DocumentStream::new(adapter, directory, self.wait))
+    }
+
+    fn adapter_builder(
+        &self,
+        url: &str,
+    ) -> Result<(&Arc<dyn BuildAdapter + 'static>, AdapterOptions)> {
+        let domain = Url::from_str(url)?
+            .host_str()
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| Error::MissingDomain(url.into()))?;
+        let host = self.aliases.get(&domain).unwrap_or(&domain);
+        let builder = self
+            .adapters
+            .get(host)
+            .ok_or_else(|| Error::UnknownDomain(domain.clone()))?;
+        let options = AdapterOptions {
+            http: self.http.clone(),
+            base: self.bases.get(host).cloned(),
+            settings: self.settings.clone(),
+        };
+        Ok((builder, options))
+    }
+
+    /// The hosts for which an adapter is available.
+    pub fn hosts(&self) -> impl Iterator<Item = &str> {
+        self.adapters
+            .keys()
+            .chain(self.aliases.keys())
+            .map(AsRef::as_ref)
+    }
+}
+
+impl Default for Klit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct KlitBuilder {
+    adapters: HashMap<String, Arc<dyn BuildAdapter + 'static>>,
+    aliases: HashMap<String, String>,
+    bases: HashMap<String, Url>,
+    http: Http,
+    overrides: HashMap<String, Url>,
+    settings: HashMap<String, String>,
+    timeout: Option<Duration>,
+    wait: Option<Duration>,
+    wayback: Option<Wayback>,
+}
+
+impl KlitBuilder {
+    /// Register an adapter for a host, replacing any existing adapter for that host.
+    pub fn adapter(
+        mut self,
+        host: impl Into<String>,
+        adapter: impl BuildAdapter + 'static,
+    ) -> Self {
+        self.adapters.insert(host.into(), Arc::new(adapter));
+        self
+    }
+
+    /// Register an adapter defined by a recipe for each of the hosts it names.
+    pub fn recipe(mut self, recipe: Recipe) -> Self {
+        let hosts = recipe.hosts.clone();
+        let adapter = Arc::new(BuildRecipeAdapter::new(recipe));
+        for host in hosts {
+            self.adapters.insert(host, adapter.clone());
+        }
+        self
+    }
+
+    pub fn recipes(self, recipes: impl IntoIterator<Item = Recipe>) -> Self {
+        recipes.into_iter().fold(self, Self::recipe)
+    }
+
+    /// Register an adapter defined by a script for each of the hosts it names.
+    pub fn script(mut self, script: Script) -> Self {
+        let hosts = script.hosts().to_vec();
+        let adapter = Arc::new(BuildScriptAdapter::new(script));
+        for host in hosts {
+            self.adapters.insert(host, adapter.clone());
+        }
+        self
+    }
+
+    pub fn scripts(self, scripts: impl IntoIterator<Item = Script>) -> Self {
+        scripts.into_iter().fold(self, Self::script)
+    }
+
+    /// Make requests through `http`, e.g. to record or replay responses.
+    pub fn http(mut self, http: Http) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Use `base` as the base url of the site at `host`, e.g. a mirror of it.
+    ///
+    /// Urls on the host of `base` are then handled by the adapter for `host`.
+    pub fn base_url(mut self, host: impl Into<String>, base: Url) -> Self {
+        let host = host.into();
+        if let Some(alias) = base.host_str().filter(|alias| *alias != host) {
+            self.aliases.insert(alias.to_owned(), host.clone());
+        }
+        self.bases.insert(host, base);
+        self
+    }
+
+    /// Send requests for `host` to `origin` instead, e.g. a mirror or a local server.
+    ///
+    /// The adapter for `host` is still used, and urls keep their original host everywhere
+    /// but on the wire.
+    pub fn host_override(mut self, host: impl Into<String>, origin: Url) -> Self {
+        self.overrides.insert(host.into(), origin);
+        self
+    }
+
+    /// Set an adapter setting, e.g. `literotica.series` to `merge`.
+    ///
+    /// Settings are passed to every adapter; each adapter documents the ones it reads.
+    pub fn setting(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.settings.insert(key.into(), value.into());
+        self
+    }
+
+    /// Fail requests that take longer than `timeout` (by default `http::TIMEOUT`).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Wait between downloads.
+    pub fn wait(mut self, wait: Duration) -> Self {
+        self.wait = Some(wait);
+        self
+    }
+
+    /// Fall back to archived snapshots of documents that are gone from their site.
+    pub fn wayback(mut self, wayback: Wayback) -> Self {
+        self.wayback = Some(wayback);
+        self
+    }
+
+    pub fn build(self) -> Klit {
+        let http = match self.timeout {
+            Some(timeout) => self.http.with_timeout(timeout),
+            None => self.http,
+        };
+        Klit {
+            adapters: self.adapters,
+            aliases: self.aliases,
+            bases: self.bases,
+            http: http.with_overrides(self.overrides),
+            settings: Arc::new(self.settings),
+            wait: self.wait,
+            wayback: self.wayback,
+        }
+    }
+}
+
+/// Documents retrieved from a directory, downloaded as they are requested.
+pub struct DocumentStream {
+    name: String,
+    inner: BoxStream<'static, Result<Document>>,
+}
+
+impl DocumentStream {
+    fn new(
+        adapter: Box<dyn AsyncAdapter + 'static>,
+        directory: DirectoryStream,
+        wait: Option<Duration>,
+    ) -> Self {
+        let name = adapter.name().to_owned();
+        let adapter: Arc<dyn AsyncAdapter> = adapter.into();
+        // Waits go between requests, so a directory entry that failed, and so was never
+        // requested, neither waits nor makes the next one wait.
+        let mut requested = false;
+        let inner = directory
+            .then(move |url| {
+                let adapter = adapter.clone();
+                let wait = wait.filter(|_| url.is_ok() && requested);
+                requested |= url.is_ok();
+                async move {
+                    if let Some(wait) = wait {
+                        tokio::time::sleep(wait).await;
+                    }
+
+                    let url = url?;
+                    let source = url.url().to_owned();
+                    let mut document = adapter.download(url).await?;
+                    document
+                        .meta
+                        .insert_default(Meta::SourceUrl, Value::url(&source));
+                    Ok(document)
+                }
+            })
+            .boxed();
+        Self { name, inner }
+    }
+
+    /// The name of the adapter retrieving these documents.
+    pub fn adapter_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Stream for DocumentStream {
+    type Item = Result<Document>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// Blocking access to a `DocumentStream`.
+pub struct Documents {
+    stream: DocumentStream,
+    runtime: Runtime,
+}
+
+impl Documents {
+    /// The name of the adapter retrieving these documents.
+    pub fn adapter_name(&self) -> &str {
+        self.stream.adapter_name()
+    }
+}
+
+impl Iterator for Documents {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
@@ -1,19 +1,13 @@
-mod adapter;
-mod document;
-mod error;
+use std::{borrow::Cow, fs, io, path::Path, str::FromStr, time::Duration};
 
-use std::{
-    borrow::Cow, collections::HashMap, fs, path::Path, str::FromStr, thread, time::Duration,
+use klit::{
+    adapter::{http::Http, load_recipes, load_scripts, user_recipe_dir, user_script_dir, Wayback},
+    export::{calibre::Library, json::Record, opf},
+    Klit, Result,
 };
-
-use adapter::BuildAdapter;
 use structopt::StructOpt;
 use url::Url;
 
-use crate::error::Error;
-
-pub type Result<T, E = error::Error> = std::result::Result<T, E>;
-
 #[derive(Clone, Debug, StructOpt)]
 struct Opts {
     /// an item or directory to be retrieved
@@ -27,14 +21,114 @@ struct Opts {
     /// an optional wait time (added between requests)
     #[structopt(short, long)]
     wait: Option<u64>,
+
+    /// give up on a request after this many seconds (default 30)
+    #[structopt(long)]
+    timeout: Option<u64>,
+
+    /// write metadata for each saved item as json (sidecar) or ndjson on stdout (ndjson)
+    #[structopt(long, possible_values = &["sidecar", "ndjson"])]
+    emit_json: Option<EmitJson>,
+
+    /// write an OPF metadata sidecar (readable by Calibre) for each saved item
+    #[structopt(long)]
+    opf: bool,
+
+    /// lay out saved items as a Calibre library (Author/Title (id)/) with metadata.opf files
+    #[structopt(long)]
+    calibre: bool,
+
+    /// save every response retrieved to this directory as a test fixture
+    #[structopt(long, conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// answer requests from fixtures saved by --record instead of the network
+    #[structopt(long)]
+    replay: Option<String>,
+
+    /// capture every request and response to a WARC file, e.g. out.warc.gz
+    #[structopt(long, conflicts_with_all = &["replay", "from-warc"])]
+    warc: Option<String>,
+
+    /// answer requests from a WARC file captured with --warc instead of the network
+    #[structopt(long, conflicts_with_all = &["record", "replay"])]
+    from_warc: Option<String>,
+
+    /// send requests for a host to another origin, e.g. www.asstr.org=http://localhost:8080
+    #[structopt(long, number_of_values = 1)]
+    host_override: Vec<HostUrl>,
+
+    /// retrieve items that are gone from their site from the Wayback Machine instead
+    #[structopt(long)]
+    wayback: bool,
+
+    /// use another base url for a site, e.g. a mirror: www.bdsmlibrary.com=https://mirror/bdsm/
+    #[structopt(long, number_of_values = 1)]
+    base_url: Vec<HostUrl>,
+
+    /// an adapter setting, e.g. literotica.series=merge (see the adapter's documentation)
+    #[structopt(long = "option", number_of_values = 1)]
+    options: Vec<Setting>,
+}
+
+/// A host paired with a url, given as HOST=URL.
+#[derive(Clone, Debug)]
+struct HostUrl {
+    host: String,
+    url: Url,
 }
 
-impl Opts {
-    fn domain(&self) -> Result<String> {
-        Url::from_str(&self.url)?
-            .domain()
-            .map(Into::into)
-            .ok_or_else(|| Error::MissingDomain(self.url.clone()))
+impl FromStr for HostUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, url) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected HOST=URL: {}", s))?;
+        let url = Url::parse(url).map_err(|e| format!("bad url {}: {}", url, e))?;
+        Ok(HostUrl {
+            host: host.into(),
+            url,
+        })
+    }
+}
+
+/// An adapter setting, given as KEY=VALUE.
+#[derive(Clone, Debug)]
+struct Setting {
+    key: String,
+    value: String,
+}
+
+impl FromStr for Setting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE: {}", s))?;
+        Ok(Setting {
+            key: key.into(),
+            value: value.into(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EmitJson {
+    Sidecar,
+    Ndjson,
+}
+
+impl FromStr for EmitJson {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sidecar" => Ok(EmitJson::Sidecar),
+            "ndjson" => Ok(EmitJson::Ndjson),
+            _ => Err(format!("unknown json mode: {}", s)),
+        }
     }
 }
 
@@ -49,12 +143,54 @@ fn main() {
 fn run(opts: &Opts) -> Result<()> {
     use owo_colors::OwoColorize;
 
-    let domain = opts.domain()?;
-    let adapters = register_adapters();
-    let adapter = adapters
-        .get(&*domain)
-        .ok_or(Error::UnknownDomain(domain))?
-        .build();
+    let mut klit = Klit::builder();
+    if let Some(wait) = opts.wait {
+        klit = klit.wait(Duration::from_secs(wait));
+    }
+    if let Some(timeout) = opts.timeout {
+        klit = klit.timeout(Duration::from_secs(timeout));
+    }
+    for HostUrl { host, url } in &opts.host_override {
+        klit = klit.host_override(host, url.clone());
+    }
+    for HostUrl { host, url } in &opts.base_url {
+        klit = klit.base_url(host, url.clone());
+    }
+    if opts.wayback {
+        klit = klit.wayback(Wayback::new());
+    }
+    for Setting { key, value } in &opts.options {
+        klit = klit.setting(key, value);
+    }
+
+    let mut http = match (&opts.record, &opts.replay, &opts.from_warc) {
+        (Some(dir), _, _) => Http::record(dir)?,
+        (_, Some(dir), _) => Http::replay(Path::new(dir))?,
+        (_, _, Some(path)) => Http::from_warc(Path::new(path))?,
+        _ => Http::new(),
+    };
+    if let Some(path) = &opts.warc {
+        http = http.with_warc(Path::new(path))?;
+    }
+    klit = klit.http(http);
+
+    if let Some(dir) = user_recipe_dir().filter(|dir| dir.is_dir()) {
+        for recipe in load_recipes(&dir)? {
+            match recipe {
+                Ok(recipe) => klit = klit.recipe(recipe),
+                Err(e) => eprintln!("{} {}", "Warn:".yellow(), e.yellow()),
+            }
+        }
+    }
+    if let Some(dir) = user_script_dir().filter(|dir| dir.is_dir()) {
+        for script in load_scripts(&dir)? {
+            match script {
+                Ok(script) => klit = klit.script(script),
+                Err(e) => eprintln!("{} {}", "Warn:".yellow(), e.yellow()),
+            }
+        }
+    }
+    let klit = klit.build();
 
     if let Some(path) = opts.path.as_ref().map(Path::new) {
         if !path.exists() {
@@ -62,85 +198,103 @@ fn run(opts: &Opts) -> Result<()> {
         }
     }
 
-    let directory = adapter.directory(&opts.url)?;
-    let mut first_iteration = true;
+    let mut library = if opts.calibre {
+        Some(Library::open(opts.path.as_deref().unwrap_or("."))?)
+    } else {
+        None
+    };
 
-    for url in directory {
-        if let Some(wait) = opts.wait.filter(|_| !first_iteration) {
-            thread::sleep(Duration::from_secs(wait));
-        }
+    let documents = klit.documents(&opts.url)?;
+    let adapter = documents.adapter_name().to_owned();
 
-        let url = match url {
-            Ok(url) => url,
-            Err(e) => {
-                eprintln!("{} {}", "Warn:".yellow(), e.yellow());
-                continue;
-            }
-        };
-
-        let document = match adapter.download(url) {
+    for document in documents {
+        let document = match document {
             Ok(document) => document,
             Err(e) => {
                 eprintln!("{} {}", "Warn:".yellow(), e.yellow());
                 continue;
             }
         };
+        let source = document.source_url().unwrap_or_default();
 
         let extension = document.extension();
-        let filename = if !extension.is_empty() {
-            document
-                .title()
-                .map(|title| Cow::from(sanitize_title(title)))
-                .unwrap_or(Cow::Borrowed("unknown"))
-                .to_string()
-                + "."
-                + extension
-        } else {
-            document
-                .title()
-                .map(|title| Cow::from(sanitize_title(title)))
-                .unwrap_or(Cow::Borrowed("unknown"))
-                .to_string()
-        };
+        let title = document
+            .title()
+            .map(|title| Cow::from(sanitize_title(title)))
+            .unwrap_or(Cow::Borrowed("unknown"));
+        let root = opts.path.as_ref().map(Path::new).unwrap_or(Path::new(""));
+        if let Some(truncated) = document.truncated() {
+            let warning = format!("{} is incomplete: {}", title, truncated);
+            eprintln!("{} {}", "Warn:".yellow(), warning.yellow());
+        }
+
+        let (path, opf_path, calibre_id) = match library.as_mut() {
+            Some(library) => {
+                let author = document
+                    .author()
+                    .map(|author| Cow::from(sanitize_title(author)))
+                    .unwrap_or(Cow::Borrowed("Unknown"));
+                let (book, id) = library.book(&author, &title)?;
+                if !book.exists() {
+                    fs::create_dir_all(&book)?;
+                }
+                let path = book.join(with_extension(format!("{} - {}", title, author), extension));
+                (path, Some(book.join("metadata.opf")), Some(id))
+            }
 
-        let path = opts
-            .path
-            .as_ref()
-            .map(|path| {
-                let path = Path::new(path);
-                Cow::from(path.join(&filename))
-            })
-            .unwrap_or_else(|| Cow::from(Path::new(&filename)));
+            None => {
+                let path = root.join(with_extension(title.to_string(), extension));
+                let opf_path = if opts.opf {
+                    let mut opf_path = path.clone().into_os_string();
+                    opf_path.push(".opf");
+                    Some(opf_path.into())
+                } else {
+                    None
+                };
+                (path, opf_path, None)
+            }
+        };
 
         if !path.exists() || opts.overwrite {
             fs::write(&path, document.content())?;
-            println!("{}", path.display());
+            if let Some(opf_path) = opf_path {
+                fs::write(opf_path, opf::render(&document, source, calibre_id))?;
+            }
+            match opts.emit_json {
+                Some(EmitJson::Sidecar) => {
+                    let record = Record::new(source, &adapter, &path, &document);
+                    let mut sidecar = path.clone().into_os_string();
+                    sidecar.push(".json");
+                    record.write_sidecar(Path::new(&sidecar))?;
+                    println!("{}", path.display());
+                }
+
+                // Paths are part of each record, so we skip printing them separately; that way
+                // stdout remains valid ndjson.
+                Some(EmitJson::Ndjson) => {
+                    let record = Record::new(source, &adapter, &path, &document);
+                    record.write_line(io::stdout().lock())?;
+                }
+
+                None => println!("{}", path.display()),
+            }
         } else {
             eprintln!("warning: file exists: {}", path.display());
         }
-
-        if first_iteration {
-            first_iteration = false;
-        }
     }
 
     Ok(())
 }
 
-fn register_adapters() -> HashMap<&'static str, Box<dyn BuildAdapter + 'static>> {
-    use adapter::*;
-    let mut map: HashMap<_, Box<dyn BuildAdapter + 'static>> = HashMap::new();
-    map.insert("www.asstr.org", Box::new(BuildAsstrAdapter));
-    map.insert("www.bdsmlibrary.com", Box::new(BuildBdsmLibraryAdapter));
-    map.insert("www.sexstories.com", Box::new(BuildSexStoriesAdapter));
-    map.insert("www.thefetlibrary.com", Box::new(BuildFetLibraryAdapter));
-    map.insert("www.utopiastories.com", Box::new(BuildGaggedUtopiaAdapter));
-    map
+fn with_extension(name: String, extension: &str) -> String {
+    if extension.is_empty() {
+        name
+    } else {
+        name + "." + extension
+    }
 }
 
 /// Remove elements of a title that cannot appear in file paths
 fn sanitize_title(title: &str) -> String {
-    title
-        .replace(|u| u == '\\' || u == '"' || u == '?', "")
-        .replace(|u| u == '/' || u == ':', "_")
+    title.replace(['\\', '"', '?'], "").replace(['/', ':'], "_")
 }
@@ -0,0 +1,523 @@
+//! End-to-end tests of the klit binary against fake sites served locally.
+//!
+//! Each fake site runs on its own port, and klit is pointed at it with `--host-override`, so the
+//! adapters see the same urls they would see in the wild.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::Path,
+    process::{Command, Output},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A site serving fixed responses, keyed by path and query, or by path alone.
+struct MockSite {
+    origin: String,
+}
+
+struct Page {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl MockSite {
+    fn start(pages: Vec<(&str, Page)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let origin = format!("http://{}", listener.local_addr().unwrap());
+        let pages: HashMap<String, Page> = pages
+            .into_iter()
+            .map(|(path, page)| (path.to_owned(), page))
+            .collect();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap() == 0 || header == "\r\n" {
+                        break;
+                    }
+                }
+
+                let path = request.split_whitespace().nth(1).unwrap_or("/");
+                let not_found = Page {
+                    status: 404,
+                    content_type: "text/html",
+                    body: "<title>Not Found</title>".into(),
+                };
+                let page = pages
+                    .get(path)
+                    .or_else(|| pages.get(path.split('?').next().unwrap()))
+                    .unwrap_or(&not_found);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    page.status,
+                    page.content_type,
+                    page.body.len(),
+                    page.body
+                );
+            }
+        });
+
+        Self { origin }
+    }
+
+    fn host_override(&self, host: &str) -> String {
+        format!("{}={}", host, self.origin)
+    }
+}
+
+fn html(body: impl Into<String>) -> Page {
+    Page {
+        status: 200,
+        content_type: "text/html; charset=utf-8",
+        body: body.into(),
+    }
+}
+
+fn text(body: impl Into<String>) -> Page {
+    Page {
+        status: 200,
+        content_type: "text/plain; charset=utf-8",
+        body: body.into(),
+    }
+}
+
+fn klit(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_klit"))
+        .args(args)
+        // Keep user recipes and scripts out of the way.
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .env("HOME", "/nonexistent")
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn read(path: impl AsRef<Path>) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+fn bdsmlibrary(second: Page) -> MockSite {
+    MockSite::start(vec![
+        (
+            "/stories/author.php?authorid=1234",
+            html(
+                "<title>BDSM Library - Stories by Jane Roe</title>\
+                 <a href=\"/stories/story.php?storyid=501\">The Lighthouse</a>\
+                 <a href=\"/stories/story.php?storyid=502\">Winter Quarters</a>",
+            ),
+        ),
+        (
+            "/stories/wholestory.php?storyid=501",
+            html("<title>The Lighthouse</title><p>The lamp turned all night.</p>"),
+        ),
+        ("/stories/wholestory.php?storyid=502", second),
+    ])
+}
+
+static BDSMLIBRARY: &str = "https://www.bdsmlibrary.com/stories/author.php?authorid=1234";
+
+fn winter_quarters() -> Page {
+    html("<title>Winter Quarters</title><p>Snow lay on the tents.</p>")
+}
+
+#[test]
+fn asstr() {
+    let site = MockSite::start(vec![
+        (
+            "/files/Authors/Somebody/",
+            html(
+                "<table><tr><td class=\"link\"><a href=\"../\">Parent</a></td></tr>\
+                 <tr><td class=\"link\"><a href=\"Late_Train.txt\">Late_Train.txt</a></td></tr></table>",
+            ),
+        ),
+        (
+            "/files/Authors/Somebody/Late_Train.txt",
+            text("Title: The Late Train\nAuthor: Somebody\n\nThe platform was empty.\n"),
+        ),
+    ]);
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--host-override",
+        &site.host_override("www.asstr.org"),
+        "https://www.asstr.org/files/Authors/Somebody/",
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let story = read(dir.path().join("Late_Train.html"));
+    assert!(
+        story.contains("<p>The platform was empty.</p>"),
+        "{}",
+        story
+    );
+}
+
+#[test]
+fn bdsmlibrary_author() {
+    let site = bdsmlibrary(winter_quarters());
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--host-override",
+        &site.host_override("www.bdsmlibrary.com"),
+        BDSMLIBRARY,
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let lighthouse = dir.path().join("The Lighthouse.html");
+    let winter = dir.path().join("Winter Quarters.html");
+    assert!(read(&lighthouse).contains("The lamp turned all night."));
+    assert!(read(&winter).contains("Snow lay on the tents."));
+    assert_eq!(
+        stdout(&output),
+        format!("{}\n{}\n", lighthouse.display(), winter.display())
+    );
+}
+
+#[test]
+fn gaggedutopia() {
+    let site = MockSite::start(vec![
+        (
+            "/code/show_result.asp?search=basic&author=Roger",
+            html("<table><tr><td><b><a href=\"/code/show_story.asp/recid/311\">Borrowed Time</a></b></td></tr></table>"),
+        ),
+        (
+            "/code/show_story.asp/recid/311",
+            html("<head><title>Borrowed Time :: Gagged Utopia</title></head><p>The clock had stopped.</p>"),
+        ),
+    ]);
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--host-override",
+        &site.host_override("www.utopiastories.com"),
+        "https://www.utopiastories.com/code/show_result.asp?search=basic&author=Roger",
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(read(dir.path().join("Borrowed Time.html")).contains("The clock had stopped."));
+}
+
+#[test]
+fn sexstories() {
+    let site = MockSite::start(vec![
+        (
+            "/profile12345/Storyteller/",
+            html(
+                "<h3 class=\"notice\"><div class=\"left\">Storyteller</div></h3>\
+                 <table><tr><td><a href=\"/story/90001/first_light\">First Light</a></td></tr></table>",
+            ),
+        ),
+        (
+            "/story/90001/first_light",
+            html("<div class=\"story_info\"><h2>First Light\n</h2></div><p>Dawn.</p>"),
+        ),
+    ]);
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--host-override",
+        &site.host_override("www.sexstories.com"),
+        "https://www.sexstories.com/profile12345/Storyteller/",
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(read(dir.path().join("First Light.html")).contains("Dawn."));
+}
+
+#[test]
+fn thefetlibrary_multi_part() {
+    let page = |text: &str| {
+        html(format!(
+            "<title>The Fet Library :: The Glass House</title>\
+             <div class=\"container\"><div class=\"row\"><div class=\"col-12 story-content\">{}</div></div></div>\
+             <a href=\"?part=2\">2</a> <a href=\"?part=2\">Next</a>",
+            text
+        ))
+    };
+    let site = MockSite::start(vec![
+        (
+            "/authors/quill",
+            html(
+                "<div class=\"jumbotron page-title\"><div class=\"container text-center\"><div><h2>Quill</h2></div></div></div>\
+                 <div class=\"story-list-item\"><h3><a href=\"/stories/77/the-glass-house\">The Glass House</a></h3></div>",
+            ),
+        ),
+        ("/stories/77/the-glass-house", page("The house was all windows.")),
+        ("/stories/77/the-glass-house?part=2", page("Nobody drew the curtains.")),
+    ]);
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--host-override",
+        &site.host_override("www.thefetlibrary.com"),
+        "https://www.thefetlibrary.com/authors/quill",
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    let story = read(dir.path().join("The Glass House.html"));
+    assert!(story.contains("The house was all windows."), "{}", story);
+    assert!(story.contains("Nobody drew the curtains."), "{}", story);
+    assert!(
+        story.contains("By <span id=author>Quill</span>"),
+        "{}",
+        story
+    );
+}
+
+#[test]
+fn mirror_base_url() {
+    let site = bdsmlibrary(winter_quarters());
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--base-url",
+        &format!("www.bdsmlibrary.com={}/", site.origin),
+        &format!("{}/stories/author.php?authorid=1234", site.origin),
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(read(dir.path().join("Winter Quarters.html")).contains("Snow lay on the tents."));
+}
+
+#[test]
+fn existing_files_are_kept_unless_overwriting() {
+    let site = bdsmlibrary(winter_quarters());
+    let dir = tempfile::tempdir().unwrap();
+    let host_override = site.host_override("www.bdsmlibrary.com");
+    let args = [
+        "--host-override",
+        &host_override,
+        BDSMLIBRARY,
+        dir.path().to_str().unwrap(),
+    ];
+    let lighthouse = dir.path().join("The Lighthouse.html");
+
+    assert!(klit(&args).status.success());
+    fs::write(&lighthouse, "edited").unwrap();
+
+    let output = klit(&args);
+    assert!(output.status.success());
+    assert_eq!(read(&lighthouse), "edited");
+    assert!(
+        stderr(&output).contains("file exists"),
+        "{}",
+        stderr(&output)
+    );
+
+    let output = klit(&[&["--overwrite"], &args[..]].concat());
+    assert!(output.status.success());
+    assert!(read(&lighthouse).contains("The lamp turned all night."));
+}
+
+#[test]
+fn wait_between_downloads() {
+    let site = bdsmlibrary(winter_quarters());
+    let dir = tempfile::tempdir().unwrap();
+    let started = Instant::now();
+    let output = klit(&[
+        "--wait",
+        "1",
+        "--host-override",
+        &site.host_override("www.bdsmlibrary.com"),
+        BDSMLIBRARY,
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[test]
+fn missing_title() {
+    let site = bdsmlibrary(html("<p>No title here.</p>"));
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--host-override",
+        &site.host_override("www.bdsmlibrary.com"),
+        BDSMLIBRARY,
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(read(dir.path().join("unknown.html")).contains("No title here."));
+}
+
+#[test]
+fn error_pages_are_reported() {
+    let site = bdsmlibrary(Page {
+        status: 500,
+        content_type: "text/html",
+        body: "<title>Server Error</title>".into(),
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--host-override",
+        &site.host_override("www.bdsmlibrary.com"),
+        BDSMLIBRARY,
+        dir.path().to_str().unwrap(),
+    ]);
+
+    // A failed story is reported, but doesn't stop the others from being saved.
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("http status 500"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(dir.path().join("The Lighthouse.html").exists());
+    assert!(!dir.path().join("Server Error.html").exists());
+}
+
+#[test]
+fn wayback_fallback() {
+    let site = bdsmlibrary(Page {
+        status: 404,
+        content_type: "text/html",
+        body: "<title>Not Found</title>".into(),
+    });
+    let archive = MockSite::start(vec![
+        (
+            "/cdx/search/cdx",
+            Page {
+                status: 200,
+                content_type: "application/json",
+                body: r#"[["timestamp"],["20120304050607"],["20200101000000"]]"#.into(),
+            },
+        ),
+        (
+            "/web/20200101000000id_/https://www.bdsmlibrary.com/stories/wholestory.php?storyid=502",
+            winter_quarters(),
+        ),
+    ]);
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--wayback",
+        "--emit-json",
+        "sidecar",
+        "--host-override",
+        &site.host_override("www.bdsmlibrary.com"),
+        "--host-override",
+        &archive.host_override("web.archive.org"),
+        BDSMLIBRARY,
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(read(dir.path().join("Winter Quarters.html")).contains("Snow lay on the tents."));
+    let record = read(dir.path().join("Winter Quarters.html.json"));
+    assert!(
+        record.contains(r#""snapshot": "20200101000000""#),
+        "{}",
+        record
+    );
+    assert!(
+        record.contains(
+            r#""source_url": "https://www.bdsmlibrary.com/stories/wholestory.php?storyid=502""#
+        ),
+        "{}",
+        record
+    );
+
+    // Documents that are still on their site are not retrieved from the archive.
+    let record = read(dir.path().join("The Lighthouse.html.json"));
+    assert!(!record.contains("snapshot"), "{}", record);
+}
+
+#[test]
+fn warc_capture_and_replay() {
+    let site = bdsmlibrary(winter_quarters());
+    let dir = tempfile::tempdir().unwrap();
+    let warc = dir.path().join("out.warc.gz");
+    let first = dir.path().join("first");
+    let output = klit(&[
+        "--warc",
+        warc.to_str().unwrap(),
+        "--host-override",
+        &site.host_override("www.bdsmlibrary.com"),
+        BDSMLIBRARY,
+        first.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // No host override this time: everything has to come from the capture.
+    let second = dir.path().join("second");
+    let output = klit(&[
+        "--from-warc",
+        warc.to_str().unwrap(),
+        BDSMLIBRARY,
+        second.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    for name in ["The Lighthouse.html", "Winter Quarters.html"] {
+        assert_eq!(read(first.join(name)), read(second.join(name)));
+    }
+
+    // Replayed responses aren't fetched, so there would be nothing to capture.
+    let output = klit(&[
+        "--warc",
+        dir.path().join("again.warc.gz").to_str().unwrap(),
+        "--from-warc",
+        warc.to_str().unwrap(),
+        BDSMLIBRARY,
+        second.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("cannot be used with"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn failed_directory_is_an_error() {
+    let site = MockSite::start(Vec::new());
+    let dir = tempfile::tempdir().unwrap();
+    let output = klit(&[
+        "--host-override",
+        &site.host_override("www.bdsmlibrary.com"),
+        BDSMLIBRARY,
+        dir.path().to_str().unwrap(),
+    ]);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("http status 404"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn unknown_domain_is_an_error() {
+    let output = klit(&["https://www.example.com/stories/"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("unknown domain: www.example.com"),
+        "{}",
+        stderr(&output)
+    );
+}
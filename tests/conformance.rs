@@ -0,0 +1,215 @@
+//! Adapter conformance tests.
+//!
+//! Each case runs an adapter offline against the responses recorded in `tests/fixtures/<site>`
+//! and compares the urls it finds and the documents it extracts with `tests/golden/<case>.json`.
+//! Several cases can share a site's fixtures, e.g. to retrieve it with different settings.
+//!
+//! The fixtures are synthetic: hand-written pages cut down to the markup each adapter reads,
+//! in the format `--record` writes, not captures of the live sites. They pin down what an
+//! adapter extracts from a page of a given shape, but they can't catch a site changing its
+//! layout; only a fresh recording can.
+//!
+//! To replace a site's fixtures with real responses, run klit against it with `--record`:
+//!
+//! ```text
+//! klit --record tests/fixtures/<site> <url> /tmp/out
+//! ```
+//!
+//! then regenerate the golden files with `KLIT_UPDATE_GOLDEN=1 cargo test --test conformance`
+//! and review the diff.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use klit::{adapter::http::Http, Klit};
+use serde_json::{json, Value};
+
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Klit answering from the fixtures of `site`, with the settings given.
+fn klit(site: &str, settings: &[(&str, &str)]) -> Klit {
+    let http = Http::replay(&root().join("fixtures").join(site)).unwrap();
+    settings
+        .iter()
+        .fold(Klit::builder().http(http), |klit, (key, value)| {
+            klit.setting(*key, *value)
+        })
+        .build()
+}
+
+fn conformance(case: &str, site: &str, url: &str, settings: &[(&str, &str)]) {
+    let klit = klit(site, settings);
+    let adapter = klit.adapter(url).unwrap();
+
+    let mut urls = Vec::new();
+    let mut documents = Vec::new();
+    for context in adapter.directory(url).unwrap() {
+        let context = context.unwrap();
+        let url = context.url().to_owned();
+        let document = adapter.download(context).unwrap();
+        let meta: serde_json::Map<_, _> = document
+            .meta
+            .iter()
+            .map(|(key, value)| (key.key().to_owned(), json!(value)))
+            .collect();
+        documents.push(json!({
+            "url": url,
+            "meta": Value::Object(meta),
+            "content": document.content(),
+        }));
+        urls.push(url);
+    }
+
+    let actual = serde_json::to_string_pretty(&json!({
+        "adapter": adapter.name(),
+        "urls": urls,
+        "documents": documents,
+    }))
+    .unwrap()
+        + "\n";
+
+    let golden = root().join("golden").join(format!("{}.json", case));
+    if env::var_os("KLIT_UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        fs::write(&golden, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|_| {
+        panic!(
+            "missing {}; run with KLIT_UPDATE_GOLDEN=1 to create it",
+            golden.display()
+        )
+    });
+    assert_eq!(
+        expected,
+        actual,
+        "{} differs from {}",
+        case,
+        golden.display()
+    );
+}
+
+/// The error downloading the first document found at `url` fails with.
+fn expect_error(site: &str, url: &str, settings: &[(&str, &str)]) -> klit::Error {
+    let klit = klit(site, settings);
+    let adapter = klit.adapter(url).unwrap();
+    let context = adapter.directory(url).unwrap().next().unwrap().unwrap();
+    match adapter.download(context) {
+        Ok(_) => panic!("expected {} to fail", url),
+        Err(e) => e,
+    }
+}
+
+/// Each case ends in `;` and is either `name: url`, using the fixtures of the site of the same
+/// name, or `name(site): url`, using those of `site`. Either may be followed by settings, as
+/// `, "key" = "value"`.
+macro_rules! conformance {
+    () => {};
+    ($case:ident: $url:expr $(, $key:literal = $value:literal)*; $($rest:tt)*) => {
+        conformance! { $case(stringify!($case)): $url $(, $key = $value)*; $($rest)* }
+    };
+    ($case:ident($site:expr): $url:expr $(, $key:literal = $value:literal)*; $($rest:tt)*) => {
+        #[test]
+        fn $case() {
+            conformance(stringify!($case), $site, $url, &[$(($key, $value)),*]);
+        }
+        conformance! { $($rest)* }
+    };
+}
+
+conformance! {
+    ao3: "https://archiveofourown.org/users/inkwell/works";
+    ao3_bookmarks("ao3"): "https://archiveofourown.org/users/inkwell/bookmarks";
+    ao3_series("ao3"): "https://archiveofourown.org/series/77";
+    asstr: "https://www.asstr.org/files/Authors/Somebody/";
+    bdsmlibrary: "https://www.bdsmlibrary.com/stories/author.php?authorid=1234";
+    chyoa: "https://chyoa.com/story/The-Old-House.1000";
+    chyoa_path("chyoa"): "https://chyoa.com/story/The-Old-House.1000", "chyoa.path" = "2,2";
+    chyoa_truncated("chyoa"): "https://chyoa.com/story/The-Old-House.1000", "chyoa.max-chapters" = "2";
+    fanfiction: "https://www.fanfiction.net/u/5551/Saltmarsh";
+    fanfiction_fandom("fanfiction"): "https://www.fanfiction.net/book/Dune/";
+    fictionpress("fanfiction"): "https://www.fictionpress.com/s/3300001/2/Tin-Lanterns";
+    fictionmania: "https://fictionmania.tv/searchdisplay/authordisplay.html?word=Juniper%20Vale";
+    fictionmania_story("fictionmania"): "https://fictionmania.tv/stories/readhtmlstory.html?storyID=88001";
+    gaggedutopia: "https://www.utopiastories.com/code/show_result.asp?search=basic&author=Roger";
+    literotica: "https://www.literotica.com/authors/Marlowe/works/stories";
+    literotica_series("literotica"): "https://www.literotica.com/series/se/4821";
+    literotica_series_merged("literotica"): "https://www.literotica.com/series/se/4821",
+        "literotica.series" = "merge";
+    lushstories: "https://www.lushstories.com/profile/velvetink";
+    mcstories: "https://mcstories.com/Authors/Vesper.html";
+    mcstories_whats_new("mcstories"): "https://mcstories.com/WhatsNew.html";
+    nifty: "https://www.nifty.org/nifty/gay/college/";
+    nifty_part("nifty"): "https://www.nifty.org/nifty/gay/college/the-long-semester/the-long-semester-2";
+    royalroad: "https://www.royalroad.com/profile/9001/fictions";
+    royalroad_since_chapter("royalroad"):
+        "https://www.royalroad.com/fiction/44012/ashfall-cartographer/chapter/700102/2-survey",
+        "royalroad.since" = "700102";
+    royalroad_since_date("royalroad"): "https://www.royalroad.com/fiction/44012/ashfall-cartographer",
+        "royalroad.since" = "2023-05-14";
+    sexstories: "https://www.sexstories.com/profile12345/Storyteller/";
+    storiesonline("storiesonline"): "https://storiesonline.net/a/Marlowe",
+        "storiesonline.username" = "reader@example.com", "storiesonline.password" = "hunter2";
+    storiesonline_series("storiesonline"): "https://storiesonline.net/series/55/coastal-tales",
+        "storiesonline.username" = "reader@example.com", "storiesonline.password" = "hunter2";
+    thefetlibrary: "https://www.thefetlibrary.com/authors/quill";
+    wattpad: "https://www.wattpad.com/user/quillfeather";
+    wattpad_part("wattpad"): "https://www.wattpad.com/9001-the-harbour-part-two-the-tide";
+}
+
+/// A challenge page is an error rather than a document.
+#[test]
+fn fanfiction_challenge() {
+    let url = "https://www.fanfiction.net/s/13900009/1/";
+    let error = expect_error("fanfiction", url, &[]);
+    assert!(matches!(error, klit::Error::Challenge(_)), "{}", error);
+}
+
+/// Without an account, a story shown only in part is an error rather than a short document.
+#[test]
+fn storiesonline_login_required() {
+    let url = "https://storiesonline.net/s/1001/the-lighthouse-keeper";
+    let error = expect_error("storiesonline_anonymous", url, &[]);
+    assert!(matches!(error, klit::Error::LoginRequired(_)), "{}", error);
+}
+
+/// A path that doesn't parse fails rather than following some other path.
+#[test]
+fn chyoa_bad_path() {
+    let url = "https://chyoa.com/story/The-Old-House.1000";
+    for path in ["1,x,2", "1,,2", "0"] {
+        let error = expect_error("chyoa", url, &[("chyoa.path", path)]);
+        assert!(matches!(error, klit::Error::Setting(..)), "{}", error);
+    }
+    let error = expect_error("chyoa", url, &[("chyoa.max-chapters", "lots")]);
+    assert!(matches!(error, klit::Error::Setting(..)), "{}", error);
+}
+
+/// A story taken down keeps its details, but is an error rather than an empty document.
+#[test]
+fn fictionmania_withdrawn() {
+    let url = "https://fictionmania.tv/stories/readinfo.html?storyID=88005";
+    let error = expect_error("fictionmania", url, &[]);
+    assert!(matches!(error, klit::Error::NoStory(_)), "{}", error);
+}
+
+/// Picking up after the last chapter there is leaves nothing to save.
+#[test]
+fn royalroad_no_new_chapters() {
+    let url = "https://www.royalroad.com/fiction/44012/ashfall-cartographer";
+    let error = expect_error("royalroad", url, &[("royalroad.since", "700311")]);
+    assert!(matches!(error, klit::Error::NoNewChapters(_)), "{}", error);
+}
+
+/// Picking up from a chapter that isn't there fails rather than retrieving the whole fiction.
+#[test]
+fn royalroad_unknown_chapter() {
+    let url = "https://www.royalroad.com/fiction/44012/ashfall-cartographer";
+    let error = expect_error("royalroad", url, &[("royalroad.since", "123")]);
+    assert!(matches!(error, klit::Error::Setting(..)), "{}", error);
+}